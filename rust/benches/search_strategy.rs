@@ -0,0 +1,55 @@
+use std::time::Instant;
+
+use rust_core::image_ops;
+use rust_core::optimizer::SearchStrategy;
+use rust_core::shapes::{Ellipse, Shape, Triangle};
+use rust_core::{DesmosOptimizer, FidelityMode, OptimizerConfig};
+
+const WIDTH: u32 = 128;
+const HEIGHT: u32 = 96;
+const SHAPE_COUNT: usize = 150;
+const SEEDS: [u64; 3] = [1, 2, 3];
+
+fn synthetic_image() -> Vec<u8> {
+    let mut pixels = Vec::with_capacity((WIDTH * HEIGHT * 4) as usize);
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let dx = x as f64 - WIDTH as f64 * 0.4;
+            let dy = y as f64 - HEIGHT as f64 * 0.5;
+            let ring = ((dx * dx + dy * dy).sqrt() / 6.0).sin();
+            pixels.push((x * 255 / WIDTH) as u8);
+            pixels.push((128.0 + ring * 100.0) as u8);
+            pixels.push((y * 255 / HEIGHT) as u8);
+            pixels.push(255);
+        }
+    }
+    image_ops::encode_png(&pixels, WIDTH, HEIGHT).expect("failed to encode synthetic image")
+}
+
+fn run<S: Shape>(image: &[u8], strategy: SearchStrategy, fidelity: FidelityMode) -> (f64, f64) {
+    let start = Instant::now();
+    let mut total_rmse = 0.0;
+    for seed in SEEDS {
+        let config = OptimizerConfig { search_strategy: strategy, ..OptimizerConfig::for_fidelity(fidelity) };
+        let mut optimizer = DesmosOptimizer::<S>::with_config(image, SHAPE_COUNT, config, seed).expect("failed to create optimizer");
+        while optimizer.step(SHAPE_COUNT).is_none() {}
+        total_rmse += optimizer.rmse();
+    }
+    (total_rmse / SEEDS.len() as f64, start.elapsed().as_secs_f64() / SEEDS.len() as f64)
+}
+
+fn compare<S: Shape>(name: &str, image: &[u8]) {
+    for (fidelity_name, fidelity) in [("standard", FidelityMode::Standard), ("high", FidelityMode::High)] {
+        for (strategy_name, strategy) in [("hill-climb", SearchStrategy::HillClimb), ("annealing", SearchStrategy::Annealing)] {
+            let (rmse, seconds) = run::<S>(image, strategy, fidelity);
+            println!("{:<10} {:<10} {:<12} rmse {:>8.3}  {:>7.3}s/run", name, fidelity_name, strategy_name, rmse, seconds);
+        }
+    }
+}
+
+fn main() {
+    let image = synthetic_image();
+    println!("{} shapes on a {}x{} image, averaged over {} seeds", SHAPE_COUNT, WIDTH, HEIGHT, SEEDS.len());
+    compare::<Ellipse>("ellipse", &image);
+    compare::<Triangle>("triangle", &image);
+}
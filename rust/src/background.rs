@@ -0,0 +1,112 @@
+use std::str::FromStr;
+
+use kmeans_colors::get_kmeans;
+use palette::Srgb;
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+pub const WHITE: [u8; 3] = [255, 255, 255];
+
+const DOMINANT_CLUSTERS: usize = 5;
+const KMEANS_MAX_ITERATIONS: usize = 20;
+const KMEANS_CONVERGENCE: f32 = 0.0025;
+const KMEANS_SEED: u64 = 0;
+// k-means runs on an evenly strided subset of at most this many pixels.
+const KMEANS_MAX_SAMPLES: usize = 65536;
+
+// Canvas color the shapes are painted over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Background {
+    Mean,
+    #[default]
+    Median,
+    Dominant,
+    Color([u8; 3]),
+}
+
+impl Background {
+    pub fn resolve(&self, pixels: &[u8]) -> [u8; 3] {
+        match self {
+            Background::Mean => mean_color(pixels),
+            Background::Median => median_color(pixels),
+            Background::Dominant => dominant_color(pixels),
+            Background::Color(color) => *color,
+        }
+    }
+}
+
+impl FromStr for Background {
+    type Err = Error;
+
+    // Accepts `mean`, `median`, `dominant`, `white`, `black` or a `#rrggbb` color.
+    fn from_str(name: &str) -> Result<Self, Error> {
+        match name {
+            "mean" => Ok(Background::Mean),
+            "median" => Ok(Background::Median),
+            "dominant" => Ok(Background::Dominant),
+            "white" => Ok(Background::Color(WHITE)),
+            "black" => Ok(Background::Color([0, 0, 0])),
+            _ => {
+                let hex = name.strip_prefix('#').filter(|hex| hex.len() == 6 && hex.is_ascii());
+                let channel = |i: usize| hex.and_then(|hex| u8::from_str_radix(&hex[i..i + 2], 16).ok());
+                match (channel(0), channel(2), channel(4)) {
+                    (Some(r), Some(g), Some(b)) => Ok(Background::Color([r, g, b])),
+                    _ => Err(Error::InvalidBackground(name.to_string())),
+                }
+            }
+        }
+    }
+}
+
+fn mean_color(pixels: &[u8]) -> [u8; 3] {
+    let count = (pixels.len() / 4).max(1) as u64;
+    let mut sums = [0u64; 3];
+    for p in pixels.chunks_exact(4) {
+        for (sum, &value) in sums.iter_mut().zip(p) {
+            *sum += value as u64;
+        }
+    }
+    sums.map(|sum| ((sum + count / 2) / count) as u8)
+}
+
+// Per-channel median from 256-bin histograms.
+fn median_color(pixels: &[u8]) -> [u8; 3] {
+    let mut histograms = [[0usize; 256]; 3];
+    for p in pixels.chunks_exact(4) {
+        for (histogram, &value) in histograms.iter_mut().zip(p) {
+            histogram[value as usize] += 1;
+        }
+    }
+    let half = pixels.len() / 4 / 2;
+    histograms.map(|histogram| {
+        let mut seen = 0;
+        histogram.iter().position(|&count| {
+            seen += count;
+            seen > half
+        }).unwrap_or(255) as u8
+    })
+}
+
+// Center of the largest k-means cluster.
+fn dominant_color(pixels: &[u8]) -> [u8; 3] {
+    let stride = (pixels.len() / 4).div_ceil(KMEANS_MAX_SAMPLES).max(1);
+    let samples: Vec<Srgb<f32>> = pixels
+        .chunks_exact(4)
+        .step_by(stride)
+        .map(|p| Srgb::new(p[0], p[1], p[2]).into_format())
+        .collect();
+    if samples.is_empty() {
+        return WHITE;
+    }
+
+    let result = get_kmeans(DOMINANT_CLUSTERS, KMEANS_MAX_ITERATIONS, KMEANS_CONVERGENCE, false, &samples, KMEANS_SEED);
+    let mut counts = vec![0usize; result.centroids.len()];
+    for &index in &result.indices {
+        counts[index as usize] += 1;
+    }
+    let largest = (0..counts.len()).max_by_key(|&i| counts[i]).unwrap_or(0);
+    let color: Srgb<u8> = result.centroids[largest].into_format();
+    [color.red, color.green, color.blue]
+}
@@ -0,0 +1,63 @@
+use std::sync::OnceLock;
+
+use palette::{LinSrgb, Srgb};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+const ENCODE_TABLE_SIZE: usize = 4096;
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BlendMode {
+    #[default]
+    Srgb,
+    Linear,
+}
+
+// Linear-light value of each sRGB byte, shared by blending and the perceptual metrics.
+pub(crate) fn srgb_to_linear() -> &'static [f64; 256] {
+    static TABLE: OnceLock<[f64; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| std::array::from_fn(|i| Srgb::new(i as u8, 0, 0).into_linear::<f64>().red))
+}
+
+pub(crate) struct Blender {
+    mode: BlendMode,
+    to_linear: &'static [f64; 256],
+    from_linear: Vec<u8>,
+}
+
+impl Blender {
+    pub fn new(mode: BlendMode) -> Self {
+        let to_linear = srgb_to_linear();
+        let from_linear = (0..ENCODE_TABLE_SIZE)
+            .map(|i| {
+                let linear = i as f64 / (ENCODE_TABLE_SIZE - 1) as f64;
+                Srgb::<u8>::from_linear(LinSrgb::new(linear, 0.0, 0.0)).red
+            })
+            .collect();
+        Blender { mode, to_linear, from_linear }
+    }
+
+    pub fn mode(&self) -> BlendMode {
+        self.mode
+    }
+
+    pub fn decode(&self, value: u8) -> f64 {
+        self.to_linear[value as usize]
+    }
+
+    pub fn encode(&self, value: f64) -> u8 {
+        self.from_linear[(value.clamp(0.0, 1.0) * (ENCODE_TABLE_SIZE - 1) as f64).round() as usize]
+    }
+
+    // Composites `color` over `current` with the given opacity.
+    #[inline]
+    pub fn blend(&self, current: u8, color: u8, alpha: f64) -> u8 {
+        match self.mode {
+            BlendMode::Srgb => (current as f64 * (1.0 - alpha) + color as f64 * alpha) as u8,
+            BlendMode::Linear => self.encode(self.decode(current) * (1.0 - alpha) + self.decode(color) * alpha),
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::background::Background;
+use crate::blend::BlendMode;
+use crate::metric::ErrorMetric;
+use crate::optimizer::SearchStrategy;
+use crate::saliency::AutoImportance;
+use crate::stop::StopCriteria;
+use crate::Error;
+
+const STANDARD_MULTIPLIER: usize = 1;
+const HIGH_FIDELITY_MULTIPLIER: usize = 3;
+const SUPER_FIDELITY_MULTIPLIER: usize = 10;
+const HYPER_FIDELITY_MULTIPLIER: usize = 100;
+const MAX_PYRAMID_LEVELS: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum FidelityMode {
+    #[default]
+    Standard,
+    High,
+    Super,
+    Hyper,
+    Custom { trials: usize, steps: usize },
+}
+
+impl FidelityMode {
+    pub fn budget(&self) -> (usize, usize) {
+        let defaults = OptimizerConfig::default();
+        let multiplier = match self {
+            FidelityMode::Standard => STANDARD_MULTIPLIER,
+            FidelityMode::High => HIGH_FIDELITY_MULTIPLIER,
+            FidelityMode::Super => SUPER_FIDELITY_MULTIPLIER,
+            FidelityMode::Hyper => HYPER_FIDELITY_MULTIPLIER,
+            FidelityMode::Custom { trials, steps } => return (*trials, *steps),
+        };
+        (defaults.random_trials * multiplier, defaults.hill_climb_steps * multiplier)
+    }
+}
+
+impl TryFrom<u8> for FidelityMode {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Error> {
+        match value {
+            0 => Ok(FidelityMode::Standard),
+            1 => Ok(FidelityMode::High),
+            2 => Ok(FidelityMode::Super),
+            3 => Ok(FidelityMode::Hyper),
+            _ => Err(Error::InvalidFidelity(value.to_string())),
+        }
+    }
+}
+
+impl FromStr for FidelityMode {
+    type Err = Error;
+
+    // Accepts the preset names or `custom:<trials>,<steps>`.
+    fn from_str(name: &str) -> Result<Self, Error> {
+        match name {
+            "standard" => Ok(FidelityMode::Standard),
+            "high" => Ok(FidelityMode::High),
+            "super" => Ok(FidelityMode::Super),
+            "hyper" => Ok(FidelityMode::Hyper),
+            _ => {
+                let invalid = || Error::InvalidFidelity(name.to_string());
+                let (trials, steps) = name.strip_prefix("custom:").and_then(|budget| budget.split_once(',')).ok_or_else(invalid)?;
+                Ok(FidelityMode::Custom {
+                    trials: trials.trim().parse().map_err(|_| invalid())?,
+                    steps: steps.trim().parse().map_err(|_| invalid())?,
+                })
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct OptimizerConfig {
+    pub random_trials: usize,
+    pub hill_climb_steps: usize,
+    pub search_strategy: SearchStrategy,
+    pub error_metric: ErrorMetric,
+    pub blend_mode: BlendMode,
+    pub solve_alpha: bool,
+    pub background: Background,
+    pub auto_importance: AutoImportance,
+    pub importance_center_bias: f64,
+    pub importance_floor: f64,
+    pub annealing_temperature_ratio: f64,
+    pub error_tile_size: u32,
+    pub refine_steps: usize,
+    pub mid_stage_threshold: f64,
+    pub late_stage_threshold: f64,
+    pub early_stage_max_radius: f64,
+    pub mid_stage_max_radius: f64,
+    pub late_stage_max_radius: f64,
+    pub min_alpha_threshold: f64,
+    pub max_working_size: Option<u32>,
+    pub pyramid_levels: usize,
+    pub mutation: MutationConfig,
+    pub stop: StopCriteria,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MutationConfig {
+    pub initial_max_size: f64,
+    pub initial_alpha_min: u8,
+    pub initial_alpha_max: u8,
+    pub min_alpha: u8,
+    pub max_alpha: u8,
+    pub position_range: f64,
+    pub radius_range: f64,
+    pub angle_range: f64,
+    pub alpha_range: f64,
+}
+
+impl Default for OptimizerConfig {
+    fn default() -> Self {
+        OptimizerConfig {
+            random_trials: 40,
+            hill_climb_steps: 80,
+            search_strategy: SearchStrategy::default(),
+            error_metric: ErrorMetric::default(),
+            blend_mode: BlendMode::default(),
+            solve_alpha: true,
+            background: Background::default(),
+            auto_importance: AutoImportance::default(),
+            importance_center_bias: 0.5,
+            importance_floor: 0.2,
+            annealing_temperature_ratio: 0.005,
+            error_tile_size: 8,
+            refine_steps: 20,
+            mid_stage_threshold: 0.5,
+            late_stage_threshold: 0.8,
+            early_stage_max_radius: 200.0,
+            mid_stage_max_radius: 30.0,
+            late_stage_max_radius: 10.0,
+            min_alpha_threshold: 0.01,
+            max_working_size: None,
+            pyramid_levels: 1,
+            mutation: MutationConfig::default(),
+            stop: StopCriteria::default(),
+        }
+    }
+}
+
+impl Default for MutationConfig {
+    fn default() -> Self {
+        MutationConfig {
+            initial_max_size: 32.0,
+            initial_alpha_min: 10,
+            initial_alpha_max: 200,
+            min_alpha: 10,
+            max_alpha: 255,
+            position_range: 16.0,
+            radius_range: 8.0,
+            angle_range: 0.5,
+            alpha_range: 30.0,
+        }
+    }
+}
+
+impl OptimizerConfig {
+    pub fn for_fidelity(fidelity: FidelityMode) -> Self {
+        let (random_trials, hill_climb_steps) = fidelity.budget();
+        OptimizerConfig { random_trials, hill_climb_steps, ..OptimizerConfig::default() }
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let config: Self = serde_json::from_str(json).map_err(|e| Error::Config(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    // Rejects values the search cannot work with. Zero mutation ranges are fine and
+    // freeze that parameter.
+    pub fn validate(&self) -> Result<(), Error> {
+        let mutation = &self.mutation;
+        let ranges = [
+            ("initial_max_size", mutation.initial_max_size),
+            ("position_range", mutation.position_range),
+            ("radius_range", mutation.radius_range),
+            ("angle_range", mutation.angle_range),
+            ("alpha_range", mutation.alpha_range),
+        ];
+        for (name, value) in ranges {
+            if !value.is_finite() || value < 0.0 {
+                return Err(Error::Config(format!("mutation.{} must be a non-negative number, got {}", name, value)));
+            }
+        }
+        if self.pyramid_levels > MAX_PYRAMID_LEVELS {
+            return Err(Error::Config(format!("pyramid_levels must be at most {}, got {}", MAX_PYRAMID_LEVELS, self.pyramid_levels)));
+        }
+        if mutation.initial_alpha_min > mutation.initial_alpha_max {
+            return Err(Error::Config(format!(
+                "mutation.initial_alpha_min ({}) exceeds mutation.initial_alpha_max ({})",
+                mutation.initial_alpha_min, mutation.initial_alpha_max
+            )));
+        }
+        if mutation.min_alpha > mutation.max_alpha {
+            return Err(Error::Config(format!(
+                "mutation.min_alpha ({}) exceeds mutation.max_alpha ({})",
+                mutation.min_alpha, mutation.max_alpha
+            )));
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,29 @@
 use serde::{Serialize, Deserialize};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+use crate::background::WHITE;
+use crate::shapes::{Ellipse, Shape};
+
+const MIN_SHAPE_ID: usize = 20;
+const FOLDER_ID: &str = "8";
+const AUTHOR_ID: &str = "2";
+const INSTRUCTIONS_ID: &str = "4";
+const GITHUB_ID: &str = "6";
+const BACKGROUND_ID: &str = "10";
+const FOLDER_TITLE: &str = "Image";
+const LIST_COLOR: &str = "#000000";
+// Decimals of list values, matching the `{:.3}` of per-expression output.
+const MIN_LIST_PRECISION: usize = 3;
+// Decimals of the background bounds, which are rounded up.
+const BOUND_PRECISION: usize = 2;
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum OutputMode {
+    #[default]
+    Expressions,
+    Lists,
+}
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct DesmosState {
@@ -33,6 +58,7 @@ pub struct ExpressionList {
 
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(tag = "type")]
+#[allow(clippy::enum_variant_names, clippy::large_enum_variant)]
 pub enum Expression {
     #[serde(rename = "expression")]
     Expression(ExpressionData),
@@ -63,6 +89,8 @@ pub struct ExpressionData {
     pub id: String,
     pub color: String,
     pub latex: String,
+    #[serde(rename = "colorLatex", skip_serializing_if = "Option::is_none")]
+    pub color_latex: Option<String>,
     #[serde(rename = "folderId", skip_serializing_if = "Option::is_none")]
     pub folder_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -84,3 +112,210 @@ pub struct Domain {
     pub min: String,
     pub max: String,
 }
+
+pub(crate) struct GraphEllipse {
+    pub cx: f64,
+    pub cy: f64,
+    pub rx: f64,
+    pub ry: f64,
+    pub rot: f64,
+}
+
+pub(crate) fn to_graph_point(x: f64, y: f64, width: f64, height: f64) -> (f64, f64) {
+    let aspect = width / height;
+    ((x / width) * (20.0 * aspect) - (10.0 * aspect), -((y / height) * 20.0 - 10.0))
+}
+
+pub(crate) fn to_graph_units(shape: &Ellipse, width: f64, height: f64) -> GraphEllipse {
+    let scale_factor = 20.0 / height;
+    let (cx, cy) = to_graph_point(shape.x, shape.y, width, height);
+    GraphEllipse {
+        cx,
+        cy,
+        rx: shape.rx * scale_factor,
+        ry: shape.ry * scale_factor,
+        rot: -shape.angle,
+    }
+}
+
+// Decimals for list values on an image `height` pixels tall, which is always 20
+// graph units. Tall images get more, so a step stays under a tenth of a pixel and
+// sub-pixel radii never round to zero.
+pub(crate) fn list_precision(height: f64) -> usize {
+    ((height / 2.0).log10().ceil().max(0.0) as usize).max(MIN_LIST_PRECISION)
+}
+
+pub(crate) fn format_number(value: f64, precision: usize) -> String {
+    let s = format!("{:.*}", precision, value);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    if s == "-0" { "0".to_string() } else { s.to_string() }
+}
+
+pub(crate) fn latex_list(values: impl Iterator<Item = String>) -> String {
+    format!(r"\left[{}\right]", values.collect::<Vec<_>>().join(","))
+}
+
+// Rounds up at the bound precision so a bound never falls short of the viewport.
+fn round_up(value: f64) -> f64 {
+    let factor = 10f64.powi(BOUND_PRECISION as i32);
+    (value * factor).ceil() / factor
+}
+
+// A filled rectangle over the whole viewport, drawn below the shapes.
+fn background_expression(background: [u8; 3], xmax: f64, ymax: f64) -> Expression {
+    let [r, g, b] = background;
+    Expression::Expression(ExpressionData {
+        id: BACKGROUND_ID.to_string(),
+        folder_id: Some(FOLDER_ID.to_string()),
+        color: format!("#{:02x}{:02x}{:02x}", r, g, b),
+        color_latex: None,
+        latex: format!(
+            r"-{y}\le y\le {y}\left\{{-{x}\le x\le {x}\right\}}",
+            x = format_number(round_up(xmax), BOUND_PRECISION), y = format_number(round_up(ymax), BOUND_PRECISION)
+        ),
+        fill: Some(true),
+        lines: Some(false),
+        fill_opacity: Some("1".to_string()),
+        line_width: Some("0".to_string()),
+        domain: None,
+        parametric_domain: None,
+    })
+}
+
+fn shape_expressions<S: Shape>(shapes: &[S], width: f64, height: f64) -> Vec<Expression> {
+    shapes.iter().enumerate().map(|(i, shape)| {
+        let (r, g, b, a) = shape.color();
+        let color_hex = format!("#{:02x}{:02x}{:02x}", r, g, b);
+        let opacity = format!("{:.3}", a as f64 / 255.0);
+        
+        Expression::Expression(ExpressionData {
+            id: format!("{}", i + MIN_SHAPE_ID),
+            folder_id: Some(FOLDER_ID.to_string()),
+            color: color_hex,
+            color_latex: None,
+            latex: shape.desmos_latex(width, height),
+            fill: Some(true),
+            lines: Some(false),
+            fill_opacity: Some(opacity),
+            line_width: Some("0".to_string()),
+            domain: None,
+            parametric_domain: None,
+        })
+    }).collect()
+}
+
+fn list_expressions<S: Shape>(shapes: &[S], width: f64, height: f64) -> Vec<Expression> {
+    let (mut definitions, latex) = S::desmos_list_definitions(shapes, width, height);
+    
+    let channel = |f: fn((u8, u8, u8, u8)) -> u8| latex_list(shapes.iter().map(|s| f(s.color()).to_string()));
+    definitions.push(format!(
+        r"C=\operatorname{{rgb}}\left({},{},{}\right)",
+        channel(|c| c.0), channel(|c| c.1), channel(|c| c.2)
+    ));
+    definitions.push(format!(r"O=\frac{{{}}}{{255}}", channel(|c| c.3)));
+    
+    let mut expressions: Vec<Expression> = definitions.into_iter().enumerate().map(|(i, latex)| {
+        Expression::Expression(ExpressionData {
+            id: format!("{}", i + MIN_SHAPE_ID),
+            folder_id: Some(FOLDER_ID.to_string()),
+            color: LIST_COLOR.to_string(),
+            color_latex: None,
+            latex,
+            fill: None,
+            lines: None,
+            fill_opacity: None,
+            line_width: None,
+            domain: None,
+            parametric_domain: None,
+        })
+    }).collect();
+    
+    expressions.push(Expression::Expression(ExpressionData {
+        id: format!("{}", expressions.len() + MIN_SHAPE_ID),
+        folder_id: Some(FOLDER_ID.to_string()),
+        color: LIST_COLOR.to_string(),
+        color_latex: Some("C".to_string()),
+        latex,
+        fill: Some(true),
+        lines: Some(false),
+        fill_opacity: Some("O".to_string()),
+        line_width: Some("0".to_string()),
+        domain: None,
+        parametric_domain: None,
+    }));
+    
+    expressions
+}
+
+// A white background is left to Desmos itself.
+pub fn build_state<S: Shape>(shapes: &[S], background: [u8; 3], img_width: u32, img_height: u32, seed: u64, mode: OutputMode) -> DesmosState {
+    let width = img_width as f64;
+    let height = img_height as f64;
+    let aspect = width / height;
+    let ymin = -10.0;
+    let ymax = 10.0;
+    let xmax = 10.0 * aspect;
+    let xmin = -xmax;
+    
+    use rand::{Rng, SeedableRng};
+    let random_seed: String = rand::rngs::StdRng::seed_from_u64(seed)
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect();
+
+    let mut final_expressions = vec![
+        Expression::Text(TextData {
+            id: AUTHOR_ID.to_string(),
+            text: "Made by Bennett Lang (Bennebotix)".to_string(),
+        }),
+        Expression::Text(TextData {
+            id: INSTRUCTIONS_ID.to_string(),
+            text: "Unhide the folder to see the image (may be laggy)".to_string(),
+        }),
+        Expression::Text(TextData {
+            id: GITHUB_ID.to_string(),
+            text: "This was made using EIDOS, a simple webapp using Rust in WebAssembly.\n\nYou can check it out here:\nhttps://github.com/Bennebotix/EIDOS".to_string(),
+        }),
+        Expression::Folder(FolderData {
+            id: FOLDER_ID.to_string(),
+            title: FOLDER_TITLE.to_string(),
+            hidden: true,
+            collapsed: true,
+        }),
+    ];
+    
+    if background != WHITE {
+        final_expressions.push(background_expression(background, xmax, ymax));
+    }
+    final_expressions.extend(match mode {
+        OutputMode::Expressions => shape_expressions(shapes, width, height),
+        OutputMode::Lists => list_expressions(shapes, width, height),
+    });
+
+    DesmosState {
+        version: 11,
+        random_seed,
+        graph: GraphSettings {
+            viewport: Viewport { xmin, ymin, xmax, ymax },
+        },
+        expressions: ExpressionList { list: final_expressions }, 
+        include_function_parameters_in_random_seed: true,
+        do_not_migrate_movable_point_style: true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_precision_keeps_sub_pixel_radii() {
+        assert_eq!(list_precision(90.0), MIN_LIST_PRECISION);
+        assert_eq!(list_precision(2000.0), MIN_LIST_PRECISION);
+        for height in [600.0, 2001.0, 4000.0, 30000.0] {
+            let half_pixel = 0.5 * 20.0 / height;
+            assert_ne!(format_number(half_pixel, list_precision(height)), "0", "height {}", height);
+        }
+    }
+}
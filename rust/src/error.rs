@@ -0,0 +1,57 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    ImageLoad(image::ImageError),
+    ImageEncode(image::ImageError),
+    Serialize(serde_json::Error),
+    Config(String),
+    InvalidFidelity(String),
+    InvalidBackground(String),
+    InvalidWeight(f32),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ImageLoad(e) => write!(f, "Failed to load image: {}", e),
+            Error::ImageEncode(e) => write!(f, "Failed to encode image: {}", e),
+            Error::Serialize(e) => write!(f, "Failed to serialize state: {}", e),
+            Error::Config(e) => write!(f, "Invalid optimizer config: {}", e),
+            Error::InvalidFidelity(mode) => write!(f, "Unknown fidelity mode: {} (expected standard, high, super, hyper or custom:<trials>,<steps>)", mode),
+            Error::InvalidBackground(name) => write!(f, "Unknown background: {} (expected mean, median, dominant, white, black or #rrggbb)", name),
+            Error::InvalidWeight(weight) => write!(f, "Invalid weight: {} (expected a finite number)", weight),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ImageLoad(e) | Error::ImageEncode(e) => Some(e),
+            Error::Serialize(e) => Some(e),
+            Error::Config(_) | Error::InvalidFidelity(_) | Error::InvalidBackground(_) | Error::InvalidWeight(_) => None,
+            Error::Io(e) => Some(e),
+        }
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(e: image::ImageError) -> Self {
+        Error::ImageLoad(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Serialize(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
@@ -0,0 +1,195 @@
+use rand::Rng;
+
+// A neighbouring tile belongs to the same blob while its error is at least this
+// fraction of the sampled tile's.
+const BLOB_ERROR_RATIO: f64 = 0.75;
+
+// Per-tile error sums over the canvas, kept in a Fenwick tree so a tile can be
+// drawn proportionally to its error in O(log n) and updated after every shape.
+pub(crate) struct TileErrorMap {
+    tile_size: u32,
+    tiles_x: u32,
+    tiles_y: u32,
+    values: Vec<f64>,
+    tree: Vec<f64>,
+}
+
+impl TileErrorMap {
+    pub fn new(width: u32, height: u32, tile_size: u32) -> Self {
+        let tile_size = tile_size.max(1);
+        let tiles_x = width.div_ceil(tile_size).max(1);
+        let tiles_y = height.div_ceil(tile_size).max(1);
+        let count = (tiles_x * tiles_y) as usize;
+        TileErrorMap { tile_size, tiles_x, tiles_y, values: vec![0.0; count], tree: vec![0.0; count + 1] }
+    }
+
+    pub fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+
+    // Tile rectangle covering the pixel bounds `[min_x, max_x) x [min_y, max_y)`.
+    pub fn tiles_covering(&self, min_x: u32, max_x: u32, min_y: u32, max_y: u32) -> (u32, u32, u32, u32) {
+        (
+            min_x / self.tile_size,
+            max_x.div_ceil(self.tile_size).min(self.tiles_x),
+            min_y / self.tile_size,
+            max_y.div_ceil(self.tile_size).min(self.tiles_y),
+        )
+    }
+
+    pub fn tile_index(&self, tx: u32, ty: u32) -> usize {
+        (ty * self.tiles_x + tx) as usize
+    }
+
+    pub fn set(&mut self, tile: usize, value: f64) {
+        let value = value.max(0.0);
+        let delta = value - self.values[tile];
+        self.values[tile] = value;
+        let mut i = tile + 1;
+        while i < self.tree.len() {
+            self.tree[i] += delta;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    // Rebuilds the tree from scratch in O(n), which also drops accumulated rounding.
+    pub fn rebuild(&mut self, values: Vec<f64>) {
+        self.values = values.into_iter().map(|v| v.max(0.0)).collect();
+        self.tree[1..].copy_from_slice(&self.values);
+        self.tree[0] = 0.0;
+        for i in 1..self.tree.len() {
+            let parent = i + (i & i.wrapping_neg());
+            if parent < self.tree.len() {
+                self.tree[parent] += self.tree[i];
+            }
+        }
+    }
+
+    fn total(&self) -> f64 {
+        let mut i = self.values.len();
+        let mut sum = 0.0;
+        while i > 0 {
+            sum += self.tree[i];
+            i &= i - 1;
+        }
+        sum
+    }
+
+    // Draws a tile with probability proportional to its error, or uniformly once
+    // the canvas matches the target.
+    pub fn sample(&self, rng: &mut impl Rng) -> (u32, u32) {
+        let total = self.total();
+        let tile = if total > 0.0 {
+            let mut remaining = rng.gen_range(0.0..total);
+            let mut pos = 0;
+            let mut step = self.values.len().next_power_of_two();
+            while step > 0 {
+                let next = pos + step;
+                if next < self.tree.len() && self.tree[next] <= remaining {
+                    remaining -= self.tree[next];
+                    pos = next;
+                }
+                step >>= 1;
+            }
+            // Rounding can land on an empty tile; step back to the last one with error.
+            let last = pos.min(self.values.len() - 1);
+            (0..=last).rev().find(|&t| self.values[t] > 0.0).unwrap_or(last)
+        } else {
+            rng.gen_range(0..self.values.len())
+        };
+        (tile as u32 % self.tiles_x, tile as u32 / self.tiles_x)
+    }
+
+    // Half the extent, in pixels, of the run of high-error tiles through (tx, ty)
+    // along each axis.
+    pub fn blob_radii(&self, tx: u32, ty: u32) -> (f64, f64) {
+        let threshold = self.values[self.tile_index(tx, ty)] * BLOB_ERROR_RATIO;
+        let high = |x: u32, y: u32| self.values[self.tile_index(x, y)] >= threshold;
+        let left = (0..tx).rev().take_while(|&x| high(x, ty)).count();
+        let right = (tx + 1..self.tiles_x).take_while(|&x| high(x, ty)).count();
+        let up = (0..ty).rev().take_while(|&y| high(tx, y)).count();
+        let down = (ty + 1..self.tiles_y).take_while(|&y| high(tx, y)).count();
+        let half = self.tile_size as f64 / 2.0;
+        ((left + right + 1) as f64 * half, (up + down + 1) as f64 * half)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    const SAMPLES: usize = 40000;
+
+    // A 4x2 grid of 4 px tiles.
+    fn map_with(values: &[f64]) -> TileErrorMap {
+        let mut map = TileErrorMap::new(16, 8, 4);
+        map.rebuild(values.to_vec());
+        map
+    }
+
+    fn sample_counts(map: &TileErrorMap, seed: u64) -> Vec<usize> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut counts = vec![0; map.values.len()];
+        for _ in 0..SAMPLES {
+            let (tx, ty) = map.sample(&mut rng);
+            counts[map.tile_index(tx, ty)] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn sampling_is_proportional_to_error() {
+        let values = [0.0, 1.0, 3.0, 0.0, 4.0, 0.0, 2.0, 0.0];
+        let counts = sample_counts(&map_with(&values), 1);
+        let total: f64 = values.iter().sum();
+        for (tile, (&value, &count)) in values.iter().zip(&counts).enumerate() {
+            if value == 0.0 {
+                assert_eq!(count, 0, "zero-error tile {} was sampled", tile);
+            } else {
+                let share = count as f64 / SAMPLES as f64;
+                assert!((share - value / total).abs() < 0.01, "tile {}: share {} for weight {}", tile, share, value / total);
+            }
+        }
+    }
+
+    #[test]
+    fn zero_error_falls_back_to_uniform() {
+        let counts = sample_counts(&map_with(&[0.0; 8]), 2);
+        let expected = SAMPLES as f64 / 8.0;
+        for count in counts {
+            assert!((count as f64 - expected).abs() < expected * 0.1, "{} samples, expected about {}", count, expected);
+        }
+    }
+
+    #[test]
+    fn set_matches_rebuild() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut map = map_with(&[1.0; 8]);
+        for _ in 0..500 {
+            let tile = rng.gen_range(0..8);
+            // Negative values are stored as zero, like in `rebuild`.
+            map.set(tile, rng.gen_range(-1.0..10.0));
+            let rebuilt = map_with(&map.values);
+            for (a, b) in map.tree.iter().zip(&rebuilt.tree) {
+                assert!((a - b).abs() < 1e-9, "tree {:?} vs rebuilt {:?}", map.tree, rebuilt.tree);
+            }
+        }
+        assert!(map.values.iter().all(|&v| v >= 0.0));
+    }
+
+    #[test]
+    fn blob_radii_follow_high_error_runs() {
+        let map = map_with(&[
+            1.0, 8.0, 9.0, 6.0,
+            0.0, 8.0, 0.0, 0.0,
+        ]);
+        // From (1, 0), tiles 2 and 3 of the row and the one below reach 0.75 * 8,
+        // tile 0 does not.
+        assert_eq!(map.blob_radii(1, 0), (6.0, 4.0));
+        // From (0, 0), the whole row reaches 0.75 * 1, the empty tile below does not.
+        assert_eq!(map.blob_radii(0, 0), (8.0, 2.0));
+    }
+}
@@ -1,18 +1,31 @@
-use wasm_bindgen::prelude::*;
+use image::codecs::png::PngEncoder;
+use image::imageops::FilterType;
+use image::{ExtendedColorType, ImageEncoder, RgbaImage};
+
+use crate::Error;
+
+pub fn encode_png(pixels: &[u8], width: u32, height: u32) -> Result<Vec<u8>, Error> {
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png)
+        .write_image(pixels, width, height, ExtendedColorType::Rgba8)
+        .map_err(Error::ImageEncode)?;
+    Ok(png)
+}
+
+pub fn resize(pixels: &[u8], width: u32, height: u32, new_width: u32, new_height: u32) -> Vec<u8> {
+    let img = RgbaImage::from_raw(width, height, pixels.to_vec()).expect("pixel buffer does not match its dimensions");
+    image::imageops::resize(&img, new_width, new_height, FilterType::Triangle).into_raw()
+}
 
-#[wasm_bindgen]
 pub struct ImageProcessor {
     width: u32,
     height: u32,
     pixels: Vec<u8>,
 }
 
-#[wasm_bindgen]
 impl ImageProcessor {
-    #[wasm_bindgen(constructor)]
-    pub fn new(image_data: &[u8]) -> Result<ImageProcessor, JsValue> {
-        let img = image::load_from_memory(image_data)
-            .map_err(|e| JsValue::from_str(&format!("Failed to load image: {}", e)))?;
+    pub fn new(image_data: &[u8]) -> Result<ImageProcessor, Error> {
+        let img = image::load_from_memory(image_data)?;
         
         let rgba_img = img.to_rgba8();
         let (width, height) = rgba_img.dimensions();
@@ -33,6 +46,10 @@ impl ImageProcessor {
         self.height
     }
 
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
     pub fn get_pixels(&self) -> Vec<u8> {
         self.pixels.clone()
     }
@@ -1,67 +1,195 @@
-mod image_ops;
-mod color;
-mod segmentation;
-mod math;
-mod desmos;
-mod shapes;
-mod optimizer;
-
-use wasm_bindgen::prelude::*;
-use desmos::{DesmosState, GraphSettings, Viewport, ExpressionList};
-
-const MIN_SHAPE_ID: usize = 20;
-const FOLDER_ID: &str = "8";
-const AUTHOR_ID: &str = "2";
-const INSTRUCTIONS_ID: &str = "4";
-const GITHUB_ID: &str = "6";
-const FOLDER_TITLE: &str = "Image";
-
-#[wasm_bindgen]
-extern "C" {
-    #[wasm_bindgen(js_namespace = console)]
-    fn log(s: &str);
-}
+pub mod image_ops;
+pub mod background;
+pub mod blend;
+pub mod config;
+pub mod color;
+pub mod segmentation;
+pub mod math;
+pub mod metric;
+pub mod desmos;
+pub mod error;
+mod error_map;
+pub mod logging;
+pub mod shapes;
+pub mod optimizer;
+pub mod saliency;
+pub mod stop;
+pub mod svg;
+pub mod weights;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+pub use config::{FidelityMode, OptimizerConfig};
+pub use error::Error;
+use logging::log;
+use shapes::{Ellipse, Shape};
+use stop::{Progress, StopReason};
 
-#[wasm_bindgen]
-pub fn greet() {
-    log("Rust initiated!");
+fn rescaled<S: Shape>(mut shape: S, factor: f64) -> S {
+    if factor != 1.0 {
+        shape.rescale(factor);
+    }
+    shape
 }
 
-#[wasm_bindgen]
-pub struct DesmosOptimizer {
-    optimizer: optimizer::Optimizer,
+pub struct DesmosOptimizer<S: Shape = Ellipse> {
+    optimizer: optimizer::Optimizer<S>,
+    target_pixels: Vec<u8>,
+    weights: Option<weights::WeightMap>,
     img_width: u32,
     img_height: u32,
+    level: usize,
+    scale: f64,
     max_shapes: usize,
     current_shape_idx: usize,
-    shapes: Vec<shapes::Ellipse>,
-    fidelity_mode: u8,
+    shapes: Vec<S>,
+    seed: u64,
+    error_history: Vec<f64>,
+    level_start: usize,
+    started_at: Option<f64>,
+    stop_reason: Option<StopReason>,
 }
 
-#[wasm_bindgen]
-impl DesmosOptimizer {
-    #[wasm_bindgen(constructor)]
-    pub fn new(image_data: &[u8], max_shapes: usize, fidelity_mode: u8) -> Result<DesmosOptimizer, JsValue> {
+impl<S: Shape> DesmosOptimizer<S> {
+    pub fn new(image_data: &[u8], max_shapes: usize, fidelity: FidelityMode, seed: u64) -> Result<DesmosOptimizer<S>, Error> {
+        Self::with_config(image_data, max_shapes, OptimizerConfig::for_fidelity(fidelity), seed)
+    }
+
+    pub fn with_config(image_data: &[u8], max_shapes: usize, config: OptimizerConfig, seed: u64) -> Result<DesmosOptimizer<S>, Error> {
+        config.validate()?;
         let img_proc = image_ops::ImageProcessor::new(image_data)?;
         let width = img_proc.width();
         let height = img_proc.height();
         
-        log(&format!("Optimizer initialized: {}x{}", width, height));
+        log(&format!("Optimizer initialized: {}x{} (seed {})", width, height, seed));
 
-        let optimizer = optimizer::Optimizer::new(&img_proc.get_pixels(), width, height);
+        let background = config.background.resolve(img_proc.pixels());
+        let optimizer = optimizer::Optimizer::without_target(background, seed, config);
         
-        Ok(DesmosOptimizer {
+        let mut desmos_optimizer = DesmosOptimizer {
             optimizer,
+            target_pixels: img_proc.get_pixels(),
+            weights: None,
             img_width: width,
             img_height: height,
+            level: 0,
+            scale: 1.0,
             max_shapes,
             current_shape_idx: 0,
             shapes: Vec::new(),
-            fidelity_mode,
-        })
+            seed,
+            error_history: Vec::new(),
+            level_start: 0,
+            started_at: None,
+            stop_reason: None,
+        };
+        desmos_optimizer.enter_level(desmos_optimizer.level_for(0));
+        Ok(desmos_optimizer)
+    }
+
+    pub fn config(&self) -> &OptimizerConfig {
+        self.optimizer.config()
+    }
+
+    // Also clears a previous stop, so a run can be continued with new criteria. The
+    // time budget and the diminishing-returns window start over.
+    pub fn set_config(&mut self, config: OptimizerConfig) -> Result<(), Error> {
+        config.validate()?;
+        if config.background != self.config().background {
+            self.optimizer.set_background(config.background.resolve(&self.target_pixels));
+        }
+        self.optimizer.set_config(config);
+        self.stop_reason = None;
+        self.started_at = None;
+        self.enter_level(self.level_for(self.current_shape_idx));
+        Ok(())
+    }
+
+    pub fn set_weight_map(&mut self, image_data: &[u8]) -> Result<(), Error> {
+        self.weights = Some(weights::WeightMap::from_image(image_data, self.img_width, self.img_height)?);
+        self.apply_weights();
+        Ok(())
+    }
+
+    pub fn add_weight_rect(&mut self, x: f64, y: f64, width: f64, height: f64, weight: f32) -> Result<(), Error> {
+        self.weight_map().fill_rect(x, y, width, height, weight)?;
+        self.apply_weights();
+        Ok(())
+    }
+
+    pub fn add_weight_ellipse(&mut self, cx: f64, cy: f64, rx: f64, ry: f64, weight: f32) -> Result<(), Error> {
+        self.weight_map().fill_ellipse(cx, cy, rx, ry, weight)?;
+        self.apply_weights();
+        Ok(())
+    }
+
+    pub fn clear_weights(&mut self) {
+        self.weights = None;
+        self.apply_weights();
+    }
+
+    // Hands the weights, at working size, to the optimizer. The canvas is kept.
+    fn apply_weights(&mut self) {
+        let weights = self.weights.as_ref().map(|map| map.resized(self.optimizer.width, self.optimizer.height));
+        self.optimizer.set_weights(weights);
+    }
+
+    fn weight_map(&mut self) -> &mut weights::WeightMap {
+        let (width, height) = (self.img_width, self.img_height);
+        self.weights.get_or_insert_with(|| weights::WeightMap::uniform(width, height))
+    }
+
+    // Levels beyond the number of halvings that still leave a pixel are dropped.
+    fn pyramid_levels(&self) -> usize {
+        let longest = (self.img_width.max(self.img_height) as f64 * self.working_scale()).round().max(1.0) as u32;
+        let max_levels = (u32::BITS - longest.leading_zeros()) as usize;
+        self.config().pyramid_levels.clamp(1, max_levels)
+    }
+
+    fn level_for(&self, shape_idx: usize) -> usize {
+        (shape_idx * self.pyramid_levels() / self.max_shapes.max(1)).min(self.pyramid_levels() - 1)
     }
 
-    pub fn step(&mut self, batch_size: usize) -> bool {
+    fn working_scale(&self) -> f64 {
+        let longest = self.img_width.max(self.img_height);
+        match self.config().max_working_size {
+            Some(max_size) if max_size > 0 && max_size < longest => max_size as f64 / longest as f64,
+            _ => 1.0,
+        }
+    }
+
+    fn level_scale(&self, level: usize) -> f64 {
+        self.working_scale() * 0.5f64.powi((self.pyramid_levels() - 1 - level) as i32)
+    }
+
+    fn enter_level(&mut self, level: usize) {
+        let scale = self.level_scale(level);
+        let width = ((self.img_width as f64 * scale).round() as u32).max(1);
+        let height = ((self.img_height as f64 * scale).round() as u32).max(1);
+        let target = if width == self.img_width && height == self.img_height {
+            self.target_pixels.clone()
+        } else {
+            image_ops::resize(&self.target_pixels, self.img_width, self.img_height, width, height)
+        };
+        
+        self.scale = scale;
+        let shapes = self.working_shapes();
+        let weights = self.weights.as_ref().map(|map| map.resized(width, height));
+        self.optimizer.resample(&target, width, height, weights, &shapes);
+        self.optimizer.set_radius_scale(scale / self.level_scale(self.pyramid_levels() - 1));
+        self.level = level;
+        self.level_start = self.error_history.len();
+        
+        log(&format!("Optimizing at {}x{}", width, height));
+    }
+
+    // Adds up to `batch_size` shapes. Returns why the run ended once a stop
+    // criterion or the shape limit is hit, and `None` while there is more to do.
+    pub fn step(&mut self, batch_size: usize) -> Option<StopReason> {
+        if self.stop_reason.is_some() {
+            return self.stop_reason;
+        }
+        let started_at = *self.started_at.get_or_insert_with(stop::now_secs);
         let start = self.current_shape_idx;
         let end = (start + batch_size).min(self.max_shapes);
         
@@ -70,100 +198,209 @@ impl DesmosOptimizer {
                 log(&format!("Added shape {}/{}", i + 1, self.max_shapes));
             }
             
-            let shape = self.optimizer.add_shape(i, self.max_shapes, self.fidelity_mode);
-            self.shapes.push(shape);
+            let level = self.level_for(i);
+            if level != self.level {
+                self.enter_level(level);
+            }
+            
+            let shape = self.optimizer.add_shape(i, self.max_shapes);
+            self.shapes.push(rescaled(shape, 1.0 / self.scale));
+            self.error_history.push(self.optimizer.rmse());
+            self.current_shape_idx = i + 1;
+
+            let progress = Progress {
+                rmse: self.optimizer.rmse(),
+                psnr: self.optimizer.psnr(),
+                history: &self.error_history[self.level_start..],
+                evaluations: self.optimizer.evaluations(),
+                elapsed_secs: stop::now_secs() - started_at,
+            };
+            if let Some(reason) = self.config().stop.check(&progress) {
+                return self.stop(reason);
+            }
         }
         
-        self.current_shape_idx = end;
-        self.current_shape_idx >= self.max_shapes
+        if self.current_shape_idx >= self.max_shapes {
+            return self.stop(StopReason::ShapeLimit);
+        }
+        None
+    }
+
+    fn stop(&mut self, reason: StopReason) -> Option<StopReason> {
+        log(&format!("Stopped after {} shapes: {}", self.shapes.len(), reason));
+        self.stop_reason = Some(reason);
+        self.stop_reason
+    }
+
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.stop_reason
+    }
+
+    // Re-optimizes the shapes placed so far, each pass revisiting every shape once
+    // with the final composite as the score. Returns the RMSE improvement.
+    pub fn refine(&mut self, passes: usize) -> f64 {
+        let before = self.optimizer.rmse();
+        let mut shapes = self.working_shapes();
+        for pass in 0..passes {
+            self.optimizer.refine(&mut shapes, self.max_shapes);
+            log(&format!("Refinement pass {}/{}: RMSE {:.3}", pass + 1, passes, self.optimizer.rmse()));
+        }
+        self.store_shapes(shapes);
+        before - self.optimizer.rmse()
+    }
+
+    // Removes shapes that barely matter: those whose removal would raise the RMSE by
+    // at most `min_contribution`, then the weakest until at most `target_count` remain.
+    // Returns the number of shapes removed.
+    pub fn prune(&mut self, min_contribution: f64, target_count: Option<usize>) -> usize {
+        let before = self.optimizer.rmse();
+        let mut shapes = self.working_shapes();
+        let removed = self.optimizer.prune(&mut shapes, min_contribution, target_count);
+        self.store_shapes(shapes);
+        log(&format!("Pruned {} shapes, {} left (RMSE {:.3} -> {:.3})", removed, self.shapes.len(), before, self.optimizer.rmse()));
+        removed
+    }
+
+    // The placed shapes in the coordinates of the current working canvas.
+    fn working_shapes(&self) -> Vec<S> {
+        self.shapes.iter().map(|s| rescaled(*s, self.scale)).collect()
+    }
+
+    fn store_shapes(&mut self, shapes: Vec<S>) {
+        self.shapes = shapes.into_iter().map(|s| rescaled(s, 1.0 / self.scale)).collect();
+    }
+
+    pub fn shapes(&self) -> &[S] {
+        &self.shapes
+    }
+
+    pub fn rmse(&self) -> f64 {
+        self.optimizer.rmse()
+    }
+
+    pub fn psnr(&self) -> f64 {
+        self.optimizer.psnr()
+    }
+
+    pub fn error_history(&self) -> &[f64] {
+        &self.error_history
+    }
+
+    pub fn width(&self) -> u32 {
+        self.optimizer.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.optimizer.height
+    }
+
+    pub fn get_canvas_pixels(&self) -> Vec<u8> {
+        self.optimizer.current_pixels.clone()
+    }
+
+    pub fn render_png(&self) -> Result<Vec<u8>, Error> {
+        image_ops::encode_png(&self.optimizer.current_pixels, self.optimizer.width, self.optimizer.height)
+    }
+
+    pub fn save_png(&self, path: impl AsRef<std::path::Path>) -> Result<(), Error> {
+        std::fs::write(path, self.render_png()?)?;
+        Ok(())
     }
     
-    pub fn get_json(&self) -> Result<String, JsValue> {
-        let width = self.img_width as f64;
-        let height = self.img_height as f64;
-        let aspect = width / height;
-        let ymin = -10.0;
-        let ymax = 10.0;
-        let xmax = 10.0 * aspect;
-        let xmin = -xmax;
-        
-        use rand::Rng;
-        let seed: String = rand::thread_rng()
-            .sample_iter(&rand::distributions::Alphanumeric)
-            .take(32)
-            .map(char::from)
+    pub fn get_json(&self, mode: desmos::OutputMode) -> Result<String, Error> {
+        let state = desmos::build_state(&self.shapes, self.optimizer.background(), self.img_width, self.img_height, self.seed, mode);
+        Ok(serde_json::to_string(&state)?)
+    }
+
+    pub fn get_svg(&self) -> String {
+        svg::build_svg(&self.shapes, self.optimizer.background(), self.img_width, self.img_height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use desmos::OutputMode;
+
+    // A 48x32 gradient with a dark disc, encoded as PNG.
+    fn test_png() -> Vec<u8> {
+        let (width, height) = (48u32, 32u32);
+        let pixels: Vec<u8> = (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                let (dx, dy) = (x as f64 - 30.0, y as f64 - 14.0);
+                if dx * dx + dy * dy < 64.0 {
+                    [30, 40, 90, 255]
+                } else {
+                    [(x * 5) as u8, (y * 7) as u8, 160, 255]
+                }
+            })
             .collect();
+        image_ops::encode_png(&pixels, width, height).unwrap()
+    }
 
-        let mut final_expressions = Vec::new();
-        
-        final_expressions.push(desmos::Expression::Text(desmos::TextData {
-            id: AUTHOR_ID.to_string(),
-            text: "Made by Bennett Lang (Bennebotix)".to_string(),
-        }));
-        
-        final_expressions.push(desmos::Expression::Text(desmos::TextData {
-            id: INSTRUCTIONS_ID.to_string(),
-            text: "Unhide the folder to see the image (may be laggy)".to_string(),
-        }));
-        
-        final_expressions.push(desmos::Expression::Text(desmos::TextData {
-            id: GITHUB_ID.to_string(),
-            text: "This was made using EIDOS, a simple webapp using Rust in WebAssembly.\n\nYou can check it out here:\nhttps://github.com/Bennebotix/EIDOS".to_string(),
-        }));
-        
-        final_expressions.push(desmos::Expression::Folder(desmos::FolderData {
-            id: FOLDER_ID.to_string(),
-            title: FOLDER_TITLE.to_string(),
-            hidden: true,
-            collapsed: true,
-        }));
-        
-        for (i, shape) in self.shapes.iter().enumerate() {
-            let cx = (shape.x / width) * (20.0 * aspect) - (10.0 * aspect);
-            let cy = -((shape.y / height) * 20.0 - 10.0);
-            
-            let scale_factor = 20.0 / height;
-            let rx = shape.rx * scale_factor;
-            let ry = shape.ry * scale_factor;
-            let rot = -shape.angle;
-            
-            let cos = rot.cos();
-            let sin = rot.sin();
-            
-            let (r, g, b, a) = shape.color;
-            let color_hex = format!("#{:02x}{:02x}{:02x}", r, g, b);
-            let opacity = format!("{:.3}", a as f64 / 255.0);
-            
-            let latex = format!(
-                r#"\frac{{\left(\left(x-{cx:.3}\right)\cdot{c:.3}+\left(y-{cy:.3}\right)\cdot{s:.3}\right)^{{2}}}}{{{rx:.3}^{{2}}}}+\frac{{\left(\left(x-{cx:.3}\right)\cdot{s:.3}-\left(y-{cy:.3}\right)\cdot{c:.3}\right)^{{2}}}}{{{ry:.3}^{{2}}}}\le1"#,
-                cx=cx, cy=cy, c=cos, s=sin, rx=rx, ry=ry
-            );
-            
-            final_expressions.push(desmos::Expression::Expression(desmos::ExpressionData {
-                id: format!("{}", i + MIN_SHAPE_ID),
-                folder_id: Some(FOLDER_ID.to_string()),
-                color: color_hex,
-                latex,
-                fill: Some(true),
-                lines: Some(false),
-                fill_opacity: Some(opacity),
-                line_width: Some("0".to_string()),
-                domain: None,
-                parametric_domain: None,
-            }));
+    fn run(png: &[u8], seed: u64) -> DesmosOptimizer<Ellipse> {
+        let mut optimizer = DesmosOptimizer::new(png, 20, FidelityMode::Standard, seed).unwrap();
+        while optimizer.step(5).is_none() {}
+        optimizer
+    }
+
+    #[test]
+    fn smoke_run_exports_valid_json_and_lowers_error() {
+        let png = test_png();
+        let initial = DesmosOptimizer::<Ellipse>::new(&png, 20, FidelityMode::Standard, 1).unwrap().rmse();
+        let optimizer = run(&png, 1);
+
+        assert_eq!(optimizer.stop_reason(), Some(StopReason::ShapeLimit));
+        assert_eq!(optimizer.shapes().len(), 20);
+        assert!(optimizer.rmse() < initial, "RMSE {} did not drop below {}", optimizer.rmse(), initial);
+        for mode in [OutputMode::Expressions, OutputMode::Lists] {
+            let json = optimizer.get_json(mode).unwrap();
+            serde_json::from_str::<serde_json::Value>(&json).unwrap();
         }
+    }
 
-        let state = DesmosState {
-            version: 11,
-            random_seed: seed,
-            graph: GraphSettings {
-                viewport: Viewport { xmin, ymin, xmax, ymax },
-            },
-            expressions: ExpressionList { list: final_expressions }, 
-            include_function_parameters_in_random_seed: true,
-            do_not_migrate_movable_point_style: true,
-        };
-        
-        serde_json::to_string(&state).map_err(|e| JsValue::from_str(&e.to_string()))
+    #[test]
+    fn same_seed_reproduces_the_run() {
+        let png = test_png();
+        let (a, b, other) = (run(&png, 7), run(&png, 7), run(&png, 8));
+
+        assert_eq!(a.get_json(OutputMode::Expressions).unwrap(), b.get_json(OutputMode::Expressions).unwrap());
+        assert_eq!(a.error_history(), b.error_history());
+        // The JSON embeds the seed, so compare what the run produced.
+        assert_ne!(a.get_svg(), other.get_svg());
+        assert_ne!(a.error_history(), other.error_history());
+    }
+
+    #[test]
+    fn weight_edits_keep_the_canvas() {
+        let mut optimizer = DesmosOptimizer::<Ellipse>::new(&test_png(), 20, FidelityMode::Standard, 1).unwrap();
+        optimizer.step(10);
+        let canvas = optimizer.get_canvas_pixels();
+        let rmse = optimizer.rmse();
+
+        optimizer.add_weight_rect(0.0, 0.0, 24.0, 16.0, 5.0).unwrap();
+        assert_eq!(optimizer.get_canvas_pixels(), canvas);
+        assert_ne!(optimizer.rmse(), rmse);
+        optimizer.clear_weights();
+        assert_eq!(optimizer.get_canvas_pixels(), canvas);
+        assert_eq!(optimizer.rmse(), rmse);
+    }
+
+    #[test]
+    fn set_config_restarts_the_time_budget() {
+        let mut optimizer = DesmosOptimizer::<Ellipse>::new(&test_png(), 20, FidelityMode::Standard, 1).unwrap();
+        let mut config = optimizer.config().clone();
+        config.stop.time_budget_secs = Some(1.0);
+        optimizer.set_config(config.clone()).unwrap();
+        optimizer.step(1);
+        // Pretend the run started long ago, so the budget is spent.
+        optimizer.started_at = Some(stop::now_secs() - 1000.0);
+        assert_eq!(optimizer.step(1), Some(StopReason::TimeBudget));
+
+        config.stop.time_budget_secs = Some(60.0);
+        optimizer.set_config(config).unwrap();
+        assert_eq!(optimizer.stop_reason(), None);
+        assert_eq!(optimizer.step(1), None);
     }
 }
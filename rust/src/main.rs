@@ -0,0 +1,362 @@
+use std::io::Write;
+use std::process::ExitCode;
+
+use rust_core::background::Background;
+use rust_core::blend::BlendMode;
+use rust_core::desmos::OutputMode;
+use rust_core::metric::ErrorMetric;
+use rust_core::optimizer::SearchStrategy;
+use rust_core::saliency::AutoImportance;
+use rust_core::stop::StopCriteria;
+use rust_core::shapes::{Ellipse, Shape, ShapeKind, Triangle};
+use rust_core::{logging, math, DesmosOptimizer, FidelityMode, OptimizerConfig};
+
+const DEFAULT_SHAPE_COUNT: usize = 500;
+const LOG_INTERVAL: usize = 50;
+
+const USAGE: &str = "Usage: eidos <input> [options]
+
+Options:
+  -n, --shapes <count>    Number of shapes to place (default: 500)
+  -f, --fidelity <mode>   standard, high, super, hyper or custom:<trials>,<steps> (default: standard)
+  -c, --config <path>     Load optimizer parameters from a TOML or JSON file (replaces --fidelity)
+  -p, --primitive <kind>  ellipse or triangle (default: ellipse)
+      --search <strategy> hill-climb or annealing (default: hill-climb)
+      --metric <metric>   Error metric: rgb, lab or oklab (default: rgb)
+      --blend <mode>      Alpha blending: srgb or linear (default: srgb)
+      --background <color>  Starting canvas: mean, median, dominant, white, black or #rrggbb (default: median)
+      --importance <mode> Automatic importance map for late-stage detail: none or edges (default: none)
+      --weights <path>    Grayscale importance mask (white = full weight, black = ignored)
+      --focus <x,y,w,h,weight>          Set the error weight inside a rectangle (repeatable)
+      --focus-ellipse <cx,cy,rx,ry,weight>  Set the error weight inside an ellipse (repeatable)
+      --prune <rmse>      After the run, drop shapes whose removal raises the RMSE by at most this much
+      --prune-to <count>  After the run, drop the weakest shapes until at most this many remain
+      --refine <passes>   Re-optimize every placed shape this many times after the run (default: 0)
+      --target-rmse <rmse>  Stop once the RMSE drops to this value
+      --target-psnr <db>  Stop once the PSNR reaches this value
+      --min-improvement <rmse>  Stop once the RMSE drops by less than this per shape (averaged over 10 shapes)
+      --time-budget <secs>  Stop after this many seconds of optimization
+      --max-evaluations <count>  Stop after scoring this many candidate shapes
+      --max-size <px>     Optimize on a copy downscaled to fit this size (default: full resolution)
+      --levels <count>    Coarse-to-fine pyramid levels, halving resolution per level (default: 1)
+  -s, --seed <seed>       Seed for a reproducible run (default: random)
+      --format <format>   expressions (one per shape) or lists (compact) (default: expressions)
+  -o, --output <path>     Write the payload to a file instead of stdout
+      --svg <path>        Also export the shapes as an SVG document
+      --png <path>        Also render the approximated canvas to a PNG
+      --history <path>    Write the per-shape RMSE/PSNR history as CSV
+      --raw               Emit the raw Desmos state JSON instead of Calc.setState(...)
+  -h, --help              Show this message";
+
+enum Focus {
+    Rect([f64; 5]),
+    Ellipse([f64; 5]),
+}
+
+struct Args {
+    input: String,
+    output: Option<String>,
+    svg: Option<String>,
+    png: Option<String>,
+    history: Option<String>,
+    shapes: usize,
+    prune: Option<f64>,
+    prune_to: Option<usize>,
+    refine: usize,
+    config: OptimizerConfig,
+    seed: u64,
+    shape_kind: ShapeKind,
+    weights: Option<String>,
+    focus: Vec<Focus>,
+    mode: OutputMode,
+    raw: bool,
+}
+
+fn parse_primitive(name: &str) -> Result<ShapeKind, String> {
+    match name {
+        "ellipse" => Ok(ShapeKind::Ellipse),
+        "triangle" => Ok(ShapeKind::Triangle),
+        _ => Err(format!("Unknown primitive: {}", name)),
+    }
+}
+
+fn parse_search(name: &str) -> Result<SearchStrategy, String> {
+    match name {
+        "hill-climb" => Ok(SearchStrategy::HillClimb),
+        "annealing" => Ok(SearchStrategy::Annealing),
+        _ => Err(format!("Unknown search strategy: {}", name)),
+    }
+}
+
+fn parse_metric(name: &str) -> Result<ErrorMetric, String> {
+    match name {
+        "rgb" => Ok(ErrorMetric::Rgb),
+        "lab" => Ok(ErrorMetric::Lab),
+        "oklab" => Ok(ErrorMetric::Oklab),
+        _ => Err(format!("Unknown error metric: {}", name)),
+    }
+}
+
+fn parse_blend(name: &str) -> Result<BlendMode, String> {
+    match name {
+        "srgb" => Ok(BlendMode::Srgb),
+        "linear" => Ok(BlendMode::Linear),
+        _ => Err(format!("Unknown blend mode: {}", name)),
+    }
+}
+
+fn parse_region(value: &str) -> Result<[f64; 5], String> {
+    let numbers: Vec<f64> = value.split(',').map(|n| n.trim().parse()).collect::<Result<_, _>>()
+        .map_err(|_| format!("Invalid region: {}", value))?;
+    if numbers.iter().any(|n| !n.is_finite()) {
+        return Err(format!("Region values must be finite: {}", value));
+    }
+    numbers.try_into().map_err(|_| format!("Region needs 5 comma-separated numbers: {}", value))
+}
+
+fn parse_number(value: &str) -> Result<f64, String> {
+    value.parse().map_err(|_| format!("Invalid number: {}", value))
+}
+
+fn parse_importance(name: &str) -> Result<AutoImportance, String> {
+    match name {
+        "none" => Ok(AutoImportance::None),
+        "edges" => Ok(AutoImportance::Edges),
+        _ => Err(format!("Unknown importance mode: {}", name)),
+    }
+}
+
+fn parse_format(name: &str) -> Result<OutputMode, String> {
+    match name {
+        "expressions" => Ok(OutputMode::Expressions),
+        "lists" => Ok(OutputMode::Lists),
+        _ => Err(format!("Unknown output format: {}", name)),
+    }
+}
+
+fn load_config(path: &str) -> Result<OptimizerConfig, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let config: OptimizerConfig = if path.ends_with(".toml") {
+        toml::from_str(&contents).map_err(|e| format!("Invalid optimizer config {}: {}", path, e))?
+    } else {
+        OptimizerConfig::from_json(&contents).map_err(|e| format!("{} ({})", e, path))?
+    };
+    config.validate().map_err(|e| format!("{} ({})", e, path))?;
+    Ok(config)
+}
+
+fn parse_args() -> Result<Option<Args>, String> {
+    let mut input = None;
+    let mut output = None;
+    let mut svg = None;
+    let mut png = None;
+    let mut history = None;
+    let mut shapes = DEFAULT_SHAPE_COUNT;
+    let mut prune = None;
+    let mut prune_to = None;
+    let mut refine = 0;
+    let mut fidelity = FidelityMode::default();
+    let mut config_path = None;
+    let mut seed = rand::random();
+    let mut shape_kind = ShapeKind::default();
+    let mut strategy = None;
+    let mut metric = None;
+    let mut blend = None;
+    let mut background: Option<Background> = None;
+    let mut importance = None;
+    let mut stop = StopCriteria::default();
+    let mut max_size = None;
+    let mut levels = None;
+    let mut weights = None;
+    let mut focus = Vec::new();
+    let mut mode = OutputMode::default();
+    let mut raw = false;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("Missing value for {}", name));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-n" | "--shapes" => {
+                let count = value(&arg)?;
+                shapes = count.parse().map_err(|_| format!("Invalid shape count: {}", count))?;
+            }
+            "--prune" => {
+                let threshold = value(&arg)?;
+                prune = Some(threshold.parse().map_err(|_| format!("Invalid prune threshold: {}", threshold))?);
+            }
+            "--prune-to" => {
+                let count = value(&arg)?;
+                prune_to = Some(count.parse().map_err(|_| format!("Invalid shape count: {}", count))?);
+            }
+            "--refine" => {
+                let passes = value(&arg)?;
+                refine = passes.parse().map_err(|_| format!("Invalid pass count: {}", passes))?;
+            }
+            "-f" | "--fidelity" => fidelity = value(&arg)?.parse().map_err(|e: rust_core::Error| e.to_string())?,
+            "-c" | "--config" => config_path = Some(value(&arg)?),
+            "-s" | "--seed" => {
+                let raw_seed = value(&arg)?;
+                seed = raw_seed.parse().map_err(|_| format!("Invalid seed: {}", raw_seed))?;
+            }
+            "-p" | "--primitive" => shape_kind = parse_primitive(&value(&arg)?)?,
+            "--search" => strategy = Some(parse_search(&value(&arg)?)?),
+            "--metric" => metric = Some(parse_metric(&value(&arg)?)?),
+            "--blend" => blend = Some(parse_blend(&value(&arg)?)?),
+            "--background" => background = Some(value(&arg)?.parse().map_err(|e: rust_core::Error| e.to_string())?),
+            "--importance" => importance = Some(parse_importance(&value(&arg)?)?),
+            "--weights" => weights = Some(value(&arg)?),
+            "--focus" => focus.push(Focus::Rect(parse_region(&value(&arg)?)?)),
+            "--focus-ellipse" => focus.push(Focus::Ellipse(parse_region(&value(&arg)?)?)),
+            "--target-rmse" => stop.target_rmse = Some(parse_number(&value(&arg)?)?),
+            "--target-psnr" => stop.target_psnr = Some(parse_number(&value(&arg)?)?),
+            "--min-improvement" => stop.min_improvement = Some(parse_number(&value(&arg)?)?),
+            "--time-budget" => stop.time_budget_secs = Some(parse_number(&value(&arg)?)?),
+            "--max-evaluations" => {
+                let count = value(&arg)?;
+                stop.max_evaluations = Some(count.parse().map_err(|_| format!("Invalid evaluation count: {}", count))?);
+            }
+            "--max-size" => {
+                let size = value(&arg)?;
+                max_size = Some(size.parse().map_err(|_| format!("Invalid size: {}", size))?);
+            }
+            "--levels" => {
+                let count = value(&arg)?;
+                levels = Some(count.parse().map_err(|_| format!("Invalid level count: {}", count))?);
+            }
+            "--format" => mode = parse_format(&value(&arg)?)?,
+            "-o" | "--output" => output = Some(value(&arg)?),
+            "--svg" => svg = Some(value(&arg)?),
+            "--png" => png = Some(value(&arg)?),
+            "--history" => history = Some(value(&arg)?),
+            "--raw" => raw = true,
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+            _ if input.is_none() => input = Some(arg),
+            _ => return Err(format!("Unexpected argument: {}", arg)),
+        }
+    }
+
+    let input = input.ok_or("Missing input image")?;
+    let mut config = match &config_path {
+        Some(path) => load_config(path)?,
+        None => OptimizerConfig::for_fidelity(fidelity),
+    };
+    if let Some(strategy) = strategy {
+        config.search_strategy = strategy;
+    }
+    if let Some(metric) = metric {
+        config.error_metric = metric;
+    }
+    if let Some(blend) = blend {
+        config.blend_mode = blend;
+    }
+    if let Some(background) = background {
+        config.background = background;
+    }
+    if let Some(importance) = importance {
+        config.auto_importance = importance;
+    }
+    let defaults = &config.stop;
+    config.stop = StopCriteria {
+        target_rmse: stop.target_rmse.or(defaults.target_rmse),
+        target_psnr: stop.target_psnr.or(defaults.target_psnr),
+        min_improvement: stop.min_improvement.or(defaults.min_improvement),
+        time_budget_secs: stop.time_budget_secs.or(defaults.time_budget_secs),
+        max_evaluations: stop.max_evaluations.or(defaults.max_evaluations),
+    };
+    if max_size.is_some() {
+        config.max_working_size = max_size;
+    }
+    if let Some(levels) = levels {
+        config.pyramid_levels = levels;
+    }
+    Ok(Some(Args { input, output, svg, png, history, shapes, prune, prune_to, refine, config, seed, shape_kind, weights, focus, mode, raw }))
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let image_data = std::fs::read(&args.input).map_err(|e| format!("Failed to read {}: {}", args.input, e))?;
+
+    logging::set_sink(|message| eprintln!("{}", message));
+
+    match args.shape_kind {
+        ShapeKind::Ellipse => run_with::<Ellipse>(&args, &image_data),
+        ShapeKind::Triangle => run_with::<Triangle>(&args, &image_data),
+    }
+}
+
+fn run_with<S: Shape>(args: &Args, image_data: &[u8]) -> Result<(), String> {
+    let mut optimizer = DesmosOptimizer::<S>::with_config(image_data, args.shapes, args.config.clone(), args.seed).map_err(|e| e.to_string())?;
+    if let Some(path) = &args.weights {
+        let mask = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        optimizer.set_weight_map(&mask).map_err(|e| e.to_string())?;
+    }
+    for focus in &args.focus {
+        match *focus {
+            Focus::Rect([x, y, w, h, weight]) => optimizer.add_weight_rect(x, y, w, h, weight as f32),
+            Focus::Ellipse([cx, cy, rx, ry, weight]) => optimizer.add_weight_ellipse(cx, cy, rx, ry, weight as f32),
+        }.map_err(|e| e.to_string())?;
+    }
+    while optimizer.step(LOG_INTERVAL).is_none() {}
+    if args.prune.is_some() || args.prune_to.is_some() {
+        optimizer.prune(args.prune.unwrap_or(0.0), args.prune_to);
+    }
+    if args.refine > 0 {
+        let improvement = optimizer.refine(args.refine);
+        eprintln!("Refinement improved RMSE by {:.3}", improvement);
+    }
+    eprintln!("RMSE: {:.3}, PSNR: {:.2} dB", optimizer.rmse(), optimizer.psnr());
+
+    if let Some(path) = &args.history {
+        let mut csv = String::from("shape,rmse,psnr\n");
+        for (i, rmse) in optimizer.error_history().iter().enumerate() {
+            csv.push_str(&format!("{},{:.6},{:.6}\n", i + 1, rmse, math::psnr_with_peak(*rmse, args.config.error_metric.peak())));
+        }
+        std::fs::write(path, csv).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        eprintln!("Wrote {}", path);
+    }
+
+    if let Some(path) = &args.svg {
+        std::fs::write(path, optimizer.get_svg()).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        eprintln!("Wrote {}", path);
+    }
+
+    if let Some(path) = &args.png {
+        optimizer.save_png(path).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        eprintln!("Wrote {}", path);
+    }
+
+    let json = optimizer.get_json(args.mode).map_err(|e| e.to_string())?;
+    let payload = if args.raw { json } else { format!("Calc.setState({});", json) };
+
+    match &args.output {
+        Some(path) => {
+            std::fs::write(path, payload).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+            eprintln!("Wrote {}", path);
+        }
+        None => {
+            let mut stdout = std::io::stdout().lock();
+            writeln!(stdout, "{}", payload).map_err(|e| e.to_string())?;
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let result = match parse_args() {
+        Ok(Some(args)) => run(args),
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => Err(format!("{}\n\n{}", e, USAGE)),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1 +1,13 @@
-// This module contained unused fitting and boundary tracing logic.
+pub fn rmse(total_squared_error: f64, samples: f64) -> f64 {
+    if samples <= 0.0 {
+        return 0.0;
+    }
+    (total_squared_error / samples).sqrt()
+}
+
+pub fn psnr_with_peak(rmse: f64, peak: f64) -> f64 {
+    if rmse <= 0.0 {
+        return f64::INFINITY;
+    }
+    20.0 * (peak / rmse).log10()
+}
@@ -0,0 +1,79 @@
+use palette::{FromColor, Lab, LinSrgb, Oklab};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::blend::srgb_to_linear;
+
+const RGB_PEAK: f64 = 255.0;
+const LAB_PEAK: f64 = 100.0;
+const OKLAB_PEAK: f64 = 1.0;
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorMetric {
+    #[default]
+    Rgb,
+    Lab,
+    Oklab,
+}
+
+impl ErrorMetric {
+    // Largest per-sample difference, used as the PSNR peak. RGB errors are counted
+    // per channel, perceptual ones per pixel (Delta E).
+    pub fn peak(&self) -> f64 {
+        match self {
+            ErrorMetric::Rgb => RGB_PEAK,
+            ErrorMetric::Lab => LAB_PEAK,
+            ErrorMetric::Oklab => OKLAB_PEAK,
+        }
+    }
+
+    pub fn samples_per_pixel(&self) -> u32 {
+        match self {
+            ErrorMetric::Rgb => 3,
+            ErrorMetric::Lab | ErrorMetric::Oklab => 1,
+        }
+    }
+}
+
+pub(crate) struct PerceptualSpace {
+    metric: ErrorMetric,
+    linear: &'static [f64; 256],
+}
+
+impl PerceptualSpace {
+    pub fn new(metric: ErrorMetric) -> Option<Self> {
+        if metric == ErrorMetric::Rgb {
+            return None;
+        }
+        Some(PerceptualSpace { metric, linear: srgb_to_linear() })
+    }
+
+    pub fn convert(&self, r: u8, g: u8, b: u8) -> [f32; 3] {
+        let linear = |v: u8| self.linear[v as usize] as f32;
+        let color = LinSrgb::new(linear(r), linear(g), linear(b));
+        match self.metric {
+            ErrorMetric::Oklab => {
+                let c = Oklab::from_color(color);
+                [c.l, c.a, c.b]
+            }
+            _ => {
+                let c: Lab = Lab::from_color(color);
+                [c.l, c.a, c.b]
+            }
+        }
+    }
+
+    pub fn convert_all(&self, pixels: &[u8]) -> Vec<[f32; 3]> {
+        pixels.chunks_exact(4).map(|p| self.convert(p[0], p[1], p[2])).collect()
+    }
+}
+
+pub(crate) fn squared_distance(a: [f32; 3], b: [f32; 3]) -> f64 {
+    let dl = (a[0] - b[0]) as f64;
+    let da = (a[1] - b[1]) as f64;
+    let db = (a[2] - b[2]) as f64;
+    dl * dl + da * da + db * db
+}
@@ -1,280 +1,1021 @@
-use crate::shapes::Ellipse;
-use rand::Rng;
+use crate::math;
+use std::marker::PhantomData;
 
-const INITIAL_CANVAS_VALUE: u8 = 255;
-const LATE_STAGE_THRESHOLD: f64 = 0.8;
-const MID_STAGE_THRESHOLD: f64 = 0.5;
-const LATE_STAGE_MAX_RADIUS: f64 = 10.0;
-const MID_STAGE_MAX_RADIUS: f64 = 30.0;
-const EARLY_STAGE_MAX_RADIUS: f64 = 200.0;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
 
-const STANDARD_MULTIPLIER: usize = 1;
-const HIGH_FIDELITY_MULTIPLIER: usize = 3;
-const SUPER_FIDELITY_MULTIPLIER: usize = 10;
-const HYPER_FIDELITY_MULTIPLIER: usize = 100;
+use crate::blend::{BlendMode, Blender};
+use crate::config::{MutationConfig, OptimizerConfig};
+use crate::error_map::TileErrorMap;
+use crate::metric::{squared_distance, PerceptualSpace};
+use crate::saliency::{self, AutoImportance};
+use crate::shapes::Shape;
+use serde::{Deserialize, Serialize};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
-const BASE_RANDOM_TRIALS: usize = 40;
-const BASE_HILL_CLIMB_STEPS: usize = 80;
-const ERROR_SAMPLE_COUNT: usize = 30;
-const INITIAL_SEED_MAX_RADIUS: f64 = 15.0;
+const ANNEALING_MIN_TEMPERATURE: f64 = 1.0;
+// Relative canvas variance under a shape below which its opacity is left alone.
+const VARIANCE_EPSILON: f64 = 1e-6;
 
-const MIN_ALPHA_THRESHOLD: f64 = 0.01;
+// Mutations scored together by `walk`. Batching is speculative and never changes
+// the result, so a sequential build scores them one at a time.
+#[cfg(feature = "parallel")]
+const MUTATION_BATCH_SIZE: usize = 8;
+#[cfg(not(feature = "parallel"))]
+const MUTATION_BATCH_SIZE: usize = 1;
 
-pub struct Optimizer {
+// A mutation drawn by `walk`, with its acceptance roll and the RNG state right after.
+struct Mutation<S> {
+    shape: S,
+    roll: f64,
+    rng: StdRng,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SearchStrategy {
+    #[default]
+    HillClimb,
+    Annealing,
+}
+
+fn first_best(scores: &[f64]) -> (usize, f64) {
+    let mut best = (0, scores[0]);
+    for (i, &score) in scores.iter().enumerate().skip(1) {
+        if score < best.1 {
+            best = (i, score);
+        }
+    }
+    best
+}
+
+fn squared_error(target: &[u8], current: &[u8]) -> i64 {
+    let dr = target[0] as i64 - current[0] as i64;
+    let dg = target[1] as i64 - current[1] as i64;
+    let db = target[2] as i64 - current[2] as i64;
+    dr * dr + dg * dg + db * db
+}
+
+fn union_bounds(a: (u32, u32, u32, u32), b: (u32, u32, u32, u32)) -> (u32, u32, u32, u32) {
+    (a.0.min(b.0), a.1.max(b.1), a.2.min(b.2), a.3.max(b.3))
+}
+
+// Solving alpha overwrites whatever a mutation picked, so the search leaves it alone.
+fn search_mutation(config: &OptimizerConfig) -> MutationConfig {
+    let alpha_range = if config.solve_alpha { 0.0 } else { config.mutation.alpha_range };
+    MutationConfig { alpha_range, ..config.mutation.clone() }
+}
+
+// Copies the RGB channels of a region out of an RGBA canvas.
+fn region_pixels(canvas: &[u8], width: u32, (min_x, max_x, min_y, max_y): (u32, u32, u32, u32)) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(((max_x.saturating_sub(min_x)) * (max_y.saturating_sub(min_y)) * 3) as usize);
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            let idx = (y * width + x) as usize * 4;
+            pixels.extend_from_slice(&canvas[idx..idx+3]);
+        }
+    }
+    pixels
+}
+
+fn write_region(canvas: &mut [u8], width: u32, (min_x, max_x, min_y, max_y): (u32, u32, u32, u32), pixels: &[u8]) {
+    let mut rgb = pixels.chunks_exact(3);
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            let idx = (y * width + x) as usize * 4;
+            canvas[idx..idx+3].copy_from_slice(rgb.next().expect("region buffer matches its bounds"));
+        }
+    }
+}
+
+struct PerceptualCache {
+    space: PerceptualSpace,
+    target: Vec<[f32; 3]>,
+    current: Vec<[f32; 3]>,
+}
+
+pub struct Optimizer<S: Shape> {
     pub target_pixels: Vec<u8>,
     pub current_pixels: Vec<u8>,
     pub width: u32,
     pub height: u32,
+    background: [u8; 3],
+    rng: StdRng,
+    total_error: f64,
+    perceptual: Option<PerceptualCache>,
+    weights: Option<Vec<f32>>,
+    weight_sum: f64,
+    // Automatic importance of the target, before user weights are multiplied in.
+    importance: Option<Vec<f32>>,
+    late_weights: Option<Vec<f32>>,
+    late_stage: bool,
+    tiles: TileErrorMap,
+    blender: Blender,
+    config: OptimizerConfig,
+    // `config.mutation` as the search uses it: alpha is frozen while it is solved.
+    mutation: MutationConfig,
+    radius_scale: f64,
+    evaluations: u64,
+    _shape: PhantomData<S>,
 }
 
-impl Optimizer {
-    pub fn new(target: &[u8], width: u32, height: u32) -> Self {
-        let current = vec![INITIAL_CANVAS_VALUE; (width * height * 4) as usize];
-        
+impl<S: Shape> Optimizer<S> {
+    pub fn new(target: &[u8], width: u32, height: u32, seed: u64, config: OptimizerConfig) -> Self {
+        let mut optimizer = Self::without_target(config.background.resolve(target), seed, config);
+        optimizer.resample(target, width, height, None, &[]);
+        optimizer
+    }
+
+    // An optimizer with an empty canvas, so callers that work at a reduced size can
+    // `resample` straight to it without building caches for the full image first.
+    pub fn without_target(background: [u8; 3], seed: u64, config: OptimizerConfig) -> Self {
         Optimizer {
-            target_pixels: target.to_vec(),
-            current_pixels: current,
-            width,
-            height,
+            target_pixels: Vec::new(),
+            current_pixels: Vec::new(),
+            width: 0,
+            height: 0,
+            background,
+            rng: StdRng::seed_from_u64(seed),
+            total_error: 0.0,
+            perceptual: None,
+            weights: None,
+            weight_sum: 0.0,
+            importance: None,
+            late_weights: None,
+            late_stage: false,
+            tiles: TileErrorMap::new(0, 0, config.error_tile_size),
+            blender: Blender::new(config.blend_mode),
+            mutation: search_mutation(&config),
+            config,
+            radius_scale: 1.0,
+            evaluations: 0,
+            _shape: PhantomData,
         }
     }
-    
-    pub fn add_shape(&mut self, shape_idx: usize, max_shapes: usize, fidelity_mode: u8) -> Ellipse {
-        let progress = shape_idx as f64 / max_shapes as f64;
-        let max_radius = if progress > LATE_STAGE_THRESHOLD {
-            LATE_STAGE_MAX_RADIUS
-        } else if progress > MID_STAGE_THRESHOLD {
-            MID_STAGE_MAX_RADIUS
-        } else {
-            EARLY_STAGE_MAX_RADIUS
+
+    // Swaps in a target (and optional per-pixel weights) of a different resolution and
+    // redraws `shapes` (already in its coordinates) onto a fresh canvas. The RNG state
+    // carries over.
+    pub fn resample(&mut self, target: &[u8], width: u32, height: u32, weights: Option<Vec<f32>>, shapes: &[S]) {
+        self.target_pixels = target.to_vec();
+        self.width = width;
+        self.height = height;
+        self.current_pixels = self.blank_canvas();
+        self.weights = weights;
+        self.rebuild_error_cache();
+        for shape in shapes {
+            self.draw_shape(shape);
+        }
+    }
+
+    fn blank_canvas(&self) -> Vec<u8> {
+        let [r, g, b] = self.background;
+        [r, g, b, 255].repeat((self.width * self.height) as usize)
+    }
+
+    pub fn background(&self) -> [u8; 3] {
+        self.background
+    }
+
+    // Takes effect on the next `resample`.
+    pub fn set_background(&mut self, background: [u8; 3]) {
+        self.background = background;
+    }
+
+    pub fn set_radius_scale(&mut self, scale: f64) {
+        self.radius_scale = scale;
+    }
+
+    pub fn config(&self) -> &OptimizerConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: OptimizerConfig) {
+        if config.blend_mode != self.blender.mode() {
+            self.blender = Blender::new(config.blend_mode);
+        }
+        self.mutation = search_mutation(&config);
+        self.config = config;
+        self.rebuild_error_cache();
+    }
+
+    fn rebuild_error_cache(&mut self) {
+        self.perceptual = PerceptualSpace::new(self.config.error_metric).map(|space| PerceptualCache {
+            target: space.convert_all(&self.target_pixels),
+            current: space.convert_all(&self.current_pixels),
+            space,
+        });
+        self.importance = match self.config.auto_importance {
+            AutoImportance::None => None,
+            AutoImportance::Edges => Some(saliency::edge_importance(
+                &self.target_pixels, self.width, self.height,
+                self.config.importance_center_bias, self.config.importance_floor,
+            )),
         };
+        self.apply_weights();
+    }
 
-        let multiplier = match fidelity_mode {
-            0 => STANDARD_MULTIPLIER,
-            1 => HIGH_FIDELITY_MULTIPLIER,
-            2 => SUPER_FIDELITY_MULTIPLIER,
-            3 => HYPER_FIDELITY_MULTIPLIER,
-            _ => STANDARD_MULTIPLIER,
+    // Swaps the per-pixel weights on the current canvas without redrawing it.
+    pub fn set_weights(&mut self, weights: Option<Vec<f32>>) {
+        self.weights = weights;
+        self.apply_weights();
+    }
+
+    // Recomputes everything that depends on the weights: the error totals, the
+    // late-stage weights and the seed tiles.
+    fn apply_weights(&mut self) {
+        self.total_error = (0..(self.width * self.height) as usize).map(|i| self.pixel_error(i * 4)).sum();
+        self.weight_sum = match &self.weights {
+            Some(weights) => weights.iter().map(|&w| w as f64).sum(),
+            None => (self.width * self.height) as f64,
         };
         
-        let random_trials = BASE_RANDOM_TRIALS * multiplier;
-        let hill_climb_steps = BASE_HILL_CLIMB_STEPS * multiplier;
-        
-        let mut best_shape = self.pick_high_error_seed(max_radius);
-        let mut best_score = self.evaluate_shape(&best_shape);
-
-        for _ in 0..random_trials {
-            let shape = self.pick_high_error_seed(max_radius);
-            let score = self.evaluate_shape(&shape);
-            if score < best_score {
-                best_score = score;
-                best_shape = shape;
+        self.late_weights = self.importance.as_ref().map(|importance| match &self.weights {
+            Some(weights) => importance.iter().zip(weights).map(|(i, w)| i * w).collect(),
+            None => importance.clone(),
+        });
+        self.rebuild_tiles();
+    }
+
+    fn rebuild_tiles(&mut self) {
+        self.tiles = TileErrorMap::new(self.width, self.height, self.config.error_tile_size);
+        let (_, tiles_x, _, tiles_y) = self.tiles.tiles_covering(0, self.width, 0, self.height);
+        let mut values = vec![0.0; (tiles_x * tiles_y) as usize];
+        let tile_size = self.tiles.tile_size();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                values[self.tiles.tile_index(x / tile_size, y / tile_size)] += self.scoring_error((y * self.width + x) as usize * 4);
+            }
+        }
+        self.tiles.rebuild(values);
+    }
+
+    // Re-sums the tiles touched by a shape's bounds after it was drawn.
+    fn refresh_tiles(&mut self, (min_x, max_x, min_y, max_y): (u32, u32, u32, u32)) {
+        let (tx0, tx1, ty0, ty1) = self.tiles.tiles_covering(min_x, max_x, min_y, max_y);
+        let tile_size = self.tiles.tile_size();
+        for ty in ty0..ty1 {
+            for tx in tx0..tx1 {
+                let mut error = 0.0;
+                for y in ty * tile_size..((ty + 1) * tile_size).min(self.height) {
+                    let row = (y * self.width) as usize * 4;
+                    for x in tx * tile_size..((tx + 1) * tile_size).min(self.width) {
+                        error += self.scoring_error(row + x as usize * 4);
+                    }
+                }
+                self.tiles.set(self.tiles.tile_index(tx, ty), error);
             }
         }
+    }
+
+    // Weights used to score candidates and pick seeds. Once the small late-stage
+    // shapes start, the automatic importance map (if any) steers them to detail.
+    fn scoring_weights(&self) -> Option<&Vec<f32>> {
+        match &self.late_weights {
+            Some(late_weights) if self.late_stage => Some(late_weights),
+            _ => self.weights.as_ref(),
+        }
+    }
+
+    // Candidate shapes scored by `add_shape` so far.
+    pub fn evaluations(&self) -> u64 {
+        self.evaluations
+    }
+
+    pub fn total_error(&self) -> f64 {
+        self.total_error
+    }
+
+    pub fn rmse(&self) -> f64 {
+        let samples = self.weight_sum * self.config.error_metric.samples_per_pixel() as f64;
+        math::rmse(self.total_error, samples)
+    }
+
+    pub fn psnr(&self) -> f64 {
+        math::psnr_with_peak(self.rmse(), self.config.error_metric.peak())
+    }
+
+    fn raw_pixel_error(&self, idx: usize) -> f64 {
+        match &self.perceptual {
+            Some(cache) => squared_distance(cache.target[idx / 4], cache.current[idx / 4]),
+            None => squared_error(&self.target_pixels[idx..idx+3], &self.current_pixels[idx..idx+3]) as f64,
+        }
+    }
+
+    fn pixel_error(&self, idx: usize) -> f64 {
+        self.raw_pixel_error(idx) * self.weights.as_ref().map_or(1.0, |weights| weights[idx / 4] as f64)
+    }
+
+    fn scoring_error(&self, idx: usize) -> f64 {
+        self.raw_pixel_error(idx) * self.scoring_weights().map_or(1.0, |weights| weights[idx / 4] as f64)
+    }
+    
+    pub fn add_shape(&mut self, shape_idx: usize, max_shapes: usize) -> S {
+        let config = &self.config;
+        let progress = shape_idx as f64 / max_shapes as f64;
+        let late_stage = progress > config.late_stage_threshold;
+        let max_radius = self.stage_max_radius(progress);
         
-        let mut shape = best_shape;
-        let mut score = best_score;
+        let random_trials = config.random_trials;
+        let hill_climb_steps = config.hill_climb_steps;
+        self.evaluations += (random_trials + 1 + hill_climb_steps) as u64;
         
-        for i in 0..hill_climb_steps {
-            let mut new_shape = shape.clone();
-            new_shape.mutate(self.width, self.height, i, hill_climb_steps);
-            
-            new_shape.rx = new_shape.rx.min(max_radius);
-            new_shape.ry = new_shape.ry.min(max_radius);
-
-            let new_score = self.evaluate_shape(&new_shape);
-            if new_score < score {
-                score = new_score;
-                shape = new_shape;
+        if late_stage != self.late_stage {
+            self.late_stage = late_stage;
+            if self.late_weights.is_some() {
+                self.rebuild_tiles();
             }
         }
         
-        shape.color = self.compute_optimal_color(&shape);
+        let seeds: Vec<S> = (0..=random_trials).map(|_| self.pick_high_error_seed(max_radius)).collect();
+        let (best_idx, best_score) = first_best(&self.evaluate_all(&seeds));
+        let best_shape = seeds[best_idx];
+        
+        let mut shape = match self.config.search_strategy {
+            SearchStrategy::HillClimb => self.hill_climb(best_shape, best_score, hill_climb_steps, max_radius),
+            SearchStrategy::Annealing => self.anneal(best_shape, best_score, hill_climb_steps, max_radius),
+        };
+        
+        shape.set_color(self.compute_optimal_color(&shape));
         self.draw_shape(&shape);
         
         shape
     }
 
-    fn pick_high_error_seed(&self, max_r: f64) -> Ellipse {
-        let mut rng = rand::thread_rng();
-        let mut best_x = rng.gen_range(0.0..self.width as f64);
-        let mut best_y = rng.gen_range(0.0..self.height as f64);
-        let mut max_error = -1.0;
-
-        for _ in 0..ERROR_SAMPLE_COUNT {
-            let x = rng.gen_range(0..self.width);
-            let y = rng.gen_range(0..self.height);
-            let idx = (y * self.width + x) as usize * 4;
-            
-            let tr = self.target_pixels[idx] as i32;
-            let tg = self.target_pixels[idx+1] as i32;
-            let tb = self.target_pixels[idx+2] as i32;
-            
-            let cr = self.current_pixels[idx] as i32;
-            let cg = self.current_pixels[idx+1] as i32;
-            let cb = self.current_pixels[idx+2] as i32;
-            
-            let error = ((tr-cr).pow(2) + (tg-cg).pow(2) + (tb-cb).pow(2)) as f64;
-            
-            if error > max_error {
-                max_error = error;
-                best_x = x as f64;
-                best_y = y as f64;
+    fn stage_max_radius(&self, progress: f64) -> f64 {
+        let config = &self.config;
+        self.radius_scale * if progress > config.late_stage_threshold {
+            config.late_stage_max_radius
+        } else if progress > config.mid_stage_threshold {
+            config.mid_stage_max_radius
+        } else {
+            config.early_stage_max_radius
+        }
+    }
+
+    // Back-fitting pass: revisits `shapes` (the full stack drawn on this canvas, in
+    // order) and hill-climbs each one in place. Candidates are scored on the final
+    // composite by re-blending the shapes above them over the affected region only.
+    // Returns the reduction in total error.
+    pub fn refine(&mut self, shapes: &mut [S], max_shapes: usize) -> f64 {
+        let before = self.total_error;
+        let blank = self.blank_canvas();
+        // `current_pixels` holds the canvas below the shape being refined, so the
+        // optimal color solve sees what the shape is actually drawn on.
+        let mut composite = std::mem::replace(&mut self.current_pixels, blank);
+        let mut bounds: Vec<_> = shapes.iter().map(|s| s.pixel_bounds(self.width, self.height)).collect();
+        let steps = self.config.refine_steps;
+
+        for i in 0..shapes.len() {
+            let max_radius = self.stage_max_radius(i as f64 / max_shapes.max(1) as f64);
+            let mut shape = shapes[i];
+            for step in 0..steps {
+                let mut candidate = shape;
+                candidate.mutate(self.width, self.height, step, steps, &self.mutation, &mut self.rng);
+                candidate.limit_radius(max_radius);
+                candidate.set_color(self.compute_optimal_color(&candidate));
+                
+                let region = union_bounds(bounds[i], candidate.pixel_bounds(self.width, self.height));
+                let pixels = self.composite_region(region, Some(&candidate), &shapes[i+1..], &bounds[i+1..]);
+                let delta = self.region_error(region, &pixels) - self.region_error(region, &region_pixels(&composite, self.width, region));
+                if delta < 0.0 {
+                    write_region(&mut composite, self.width, region, &pixels);
+                    self.total_error += delta;
+                    shape = candidate;
+                    bounds[i] = shape.pixel_bounds(self.width, self.height);
+                }
             }
+            shapes[i] = shape;
+            self.paint(&shape);
         }
-        
-        let mut s = Ellipse::new_random(self.width, self.height);
-        s.x = best_x;
-        s.y = best_y;
-        s.rx = rng.gen_range(1.0..max_r.min(INITIAL_SEED_MAX_RADIUS)); 
-        s.ry = rng.gen_range(1.0..max_r.min(INITIAL_SEED_MAX_RADIUS));
-        s
-    }
-
-    fn evaluate_shape(&self, shape: &Ellipse) -> f64 {
-        let r_max = shape.rx.max(shape.ry);
-        let min_x = (shape.x - r_max).floor().max(0.0) as u32;
-        let max_x = (shape.x + r_max).ceil().min(self.width as f64) as u32;
-        let min_y = (shape.y - r_max).floor().max(0.0) as u32;
-        let max_y = (shape.y + r_max).ceil().min(self.height as f64) as u32;
-        
+
+        self.current_pixels = composite;
+        self.rebuild_error_cache();
+        before - self.total_error
+    }
+
+    // Removes shapes from `shapes` (the full stack, in order) whose removal raises the
+    // total error by at most `max_error_increase`, stopping after `max_removals`. Each
+    // contribution is measured on the final composite with earlier removals applied.
+    // Returns the number removed and the contribution measured for every shape visited.
+    fn prune_pass(&mut self, shapes: &mut Vec<S>, max_error_increase: f64, max_removals: usize) -> (usize, Vec<f64>) {
+        let blank = self.blank_canvas();
+        let mut composite = std::mem::replace(&mut self.current_pixels, blank);
+        let mut bounds: Vec<_> = shapes.iter().map(|s| s.pixel_bounds(self.width, self.height)).collect();
+        let mut contributions = Vec::with_capacity(shapes.len());
+        let mut removed = 0;
+
+        let mut i = 0;
+        while i < shapes.len() {
+            let region = bounds[i];
+            let pixels = self.composite_region(region, None, &shapes[i+1..], &bounds[i+1..]);
+            let increase = self.region_error(region, &pixels) - self.region_error(region, &region_pixels(&composite, self.width, region));
+            contributions.push(increase);
+            if removed < max_removals && increase <= max_error_increase {
+                write_region(&mut composite, self.width, region, &pixels);
+                self.total_error += increase;
+                shapes.remove(i);
+                bounds.remove(i);
+                removed += 1;
+            } else {
+                let shape = shapes[i];
+                self.paint(&shape);
+                i += 1;
+            }
+        }
+
+        self.current_pixels = composite;
+        self.rebuild_error_cache();
+        (removed, contributions)
+    }
+
+    // Drops shapes whose removal would raise the RMSE by at most `min_contribution`,
+    // then the weakest remaining ones until at most `target_count` are left. The canvas
+    // is recomposited without them. Returns the number of shapes removed.
+    pub fn prune(&mut self, shapes: &mut Vec<S>, min_contribution: f64, target_count: Option<usize>) -> usize {
+        let samples = self.weight_sum * self.config.error_metric.samples_per_pixel() as f64;
+        // (rmse + m)^2 - rmse^2 expanded, so a zero threshold stays exactly zero and
+        // hidden shapes (whose removal changes nothing) always go.
+        let rmse = self.rmse();
+        let m = min_contribution.max(-rmse);
+        let max_increase = m * (2.0 * rmse + m) * samples;
+        let (mut removed, _) = self.prune_pass(shapes, max_increase, usize::MAX);
+
+        // Contributions shift as shapes go, so remove the weakest in rounds and
+        // re-measure. Each round removes at least the weakest shape.
+        while let Some(excess) = target_count.map(|target| shapes.len().saturating_sub(target)).filter(|&excess| excess > 0) {
+            let (_, mut contributions) = self.prune_pass(shapes, f64::NEG_INFINITY, 0);
+            contributions.sort_by(|a, b| a.total_cmp(b));
+            removed += self.prune_pass(shapes, contributions[excess - 1], excess).0;
+        }
+        removed
+    }
+
+    // RGB of the region after drawing `shape` (if any) and then `above` over the
+    // canvas below.
+    fn composite_region(&self, region: (u32, u32, u32, u32), shape: Option<&S>, above: &[S], above_bounds: &[(u32, u32, u32, u32)]) -> Vec<u8> {
+        let mut pixels = region_pixels(&self.current_pixels, self.width, region);
+        let (min_x, max_x, min_y, max_y) = region;
+        let stride = (max_x - min_x) as usize;
+        let mut blend_over = |s: &S| {
+            let (r, g, b, a) = s.color();
+            let color = [r, g, b];
+            let alpha_f = a as f64 / 255.0;
+            s.for_each_span(self.width, self.height, |y, start, end| {
+                if y < min_y || y >= max_y {
+                    return;
+                }
+                let row = (y - min_y) as usize * stride;
+                for x in start.max(min_x)..end.min(max_x) {
+                    let idx = (row + (x - min_x) as usize) * 3;
+                    for c in 0..3 {
+                        pixels[idx + c] = self.blender.blend(pixels[idx + c], color[c], alpha_f);
+                    }
+                }
+            });
+        };
+        if let Some(shape) = shape {
+            blend_over(shape);
+        }
+        for (s, &b) in above.iter().zip(above_bounds) {
+            if b.0 < max_x && b.1 > min_x && b.2 < max_y && b.3 > min_y {
+                blend_over(s);
+            }
+        }
+        pixels
+    }
+
+    // Weighted error of region RGB pixels against the target.
+    fn region_error(&self, (min_x, max_x, min_y, max_y): (u32, u32, u32, u32), pixels: &[u8]) -> f64 {
+        let mut error = 0.0;
+        let mut rgb = pixels.chunks_exact(3);
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let p = (y * self.width + x) as usize;
+                let c = rgb.next().expect("region buffer matches its bounds");
+                let e = match &self.perceptual {
+                    Some(cache) => squared_distance(cache.target[p], cache.space.convert(c[0], c[1], c[2])),
+                    None => squared_error(&self.target_pixels[p * 4..p * 4 + 3], c) as f64,
+                };
+                error += self.weights.as_ref().map_or(e, |weights| e * weights[p] as f64);
+            }
+        }
+        error
+    }
+
+    // Blends a shape onto `current_pixels` without touching the error caches.
+    fn paint(&mut self, shape: &S) {
+        let (r, g, b, a) = shape.color();
+        let alpha_f = a as f64 / 255.0;
+        let width = self.width;
+        let current = &mut self.current_pixels;
+        let blender = &self.blender;
+        shape.for_each_span(self.width, self.height, |y, start, end| {
+            let row = (y * width) as usize * 4;
+            for idx in (row + start as usize * 4..row + end as usize * 4).step_by(4) {
+                current[idx] = blender.blend(current[idx], r, alpha_f);
+                current[idx+1] = blender.blend(current[idx+1], g, alpha_f);
+                current[idx+2] = blender.blend(current[idx+2], b, alpha_f);
+            }
+        });
+    }
+
+    // Draws up to `MUTATION_BATCH_SIZE` mutations of `shape` for steps `start..`,
+    // each followed by its acceptance roll when `rolls` is set.
+    fn mutations(&mut self, shape: &S, start: usize, steps: usize, max_radius: f64, rolls: bool) -> Vec<Mutation<S>> {
+        let end = (start + MUTATION_BATCH_SIZE).min(steps);
+        (start..end).map(|i| {
+            let mut new_shape = *shape;
+            new_shape.mutate(self.width, self.height, i, steps, &self.mutation, &mut self.rng);
+            new_shape.limit_radius(max_radius);
+            let roll = if rolls { self.rng.gen::<f64>() } else { 0.0 };
+            Mutation { shape: new_shape, roll, rng: self.rng.clone() }
+        }).collect()
+    }
+
+    fn hill_climb(&mut self, shape: S, score: f64, steps: usize, max_radius: f64) -> S {
+        self.walk(shape, score, steps, max_radius, None)
+    }
+
+    fn anneal(&mut self, shape: S, score: f64, steps: usize, max_radius: f64) -> S {
+        let initial_temperature = if score < f64::MAX {
+            (score.abs() * self.config.annealing_temperature_ratio).max(ANNEALING_MIN_TEMPERATURE)
+        } else {
+            ANNEALING_MIN_TEMPERATURE
+        };
+        self.walk(shape, score, steps, max_radius, Some(initial_temperature))
+    }
+
+    // Mutates `shape` for `steps` steps, moving to a mutation when it scores better
+    // or, with a temperature, passes the annealing roll. Returns the best shape seen.
+    //
+    // A batch of mutations is drawn from the current shape and scored together, then
+    // replayed in order. The batch is cut at the first accepted mutation and the RNG
+    // rewound to just after it, so the walk is exactly the one-at-a-time walk
+    // whatever the batch size.
+    fn walk(&mut self, mut shape: S, mut score: f64, steps: usize, max_radius: f64, initial_temperature: Option<f64>) -> S {
+        let mut best_shape = shape;
+        let mut best_score = score;
+        let mut i = 0;
+        while i < steps {
+            let batch = self.mutations(&shape, i, steps, max_radius, initial_temperature.is_some());
+            let candidates: Vec<S> = batch.iter().map(|m| m.shape).collect();
+            let scores = self.evaluate_all(&candidates);
+            let mut taken = batch.len();
+            for (j, (mutation, new_score)) in batch.into_iter().zip(scores).enumerate() {
+                let delta = new_score - score;
+                let accepted = delta < 0.0 || initial_temperature.is_some_and(|initial| {
+                    let temperature = initial * (1.0 - (i + j) as f64 / steps as f64);
+                    mutation.roll < (-delta / temperature).exp()
+                });
+                if accepted {
+                    score = new_score;
+                    shape = mutation.shape;
+                    if score < best_score {
+                        best_score = score;
+                        best_shape = shape;
+                    }
+                    self.rng = mutation.rng;
+                    taken = j + 1;
+                    break;
+                }
+            }
+            i += taken;
+        }
+        best_shape
+    }
+
+    // Picks a tile proportionally to its error and a pixel within it. The seed is
+    // sized to the run of high-error tiles around it.
+    fn pick_high_error_seed(&mut self, max_r: f64) -> S {
+        let (tx, ty) = self.tiles.sample(&mut self.rng);
+        let tile_size = self.tiles.tile_size();
+        let x = (tx * tile_size + self.rng.gen_range(0..tile_size)).min(self.width - 1);
+        let y = (ty * tile_size + self.rng.gen_range(0..tile_size)).min(self.height - 1);
+        let (rx, ry) = self.tiles.blob_radii(tx, ty);
+        let radii = (rx.min(max_r), ry.min(max_r));
+        S::new_seeded(x as f64, y as f64, radii, self.width, self.height, &self.config.mutation, &mut self.rng)
+    }
+
+    fn span_indices(&self, y: u32, start: u32, end: u32) -> std::iter::StepBy<std::ops::Range<usize>> {
+        let row = (y * self.width) as usize * 4;
+        (row + start as usize * 4..row + end as usize * 4).step_by(4)
+    }
+
+    #[cfg(feature = "parallel")]
+    fn evaluate_all(&self, shapes: &[S]) -> Vec<f64> {
+        shapes.par_iter().map(|shape| self.evaluate_shape(shape)).collect()
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    fn evaluate_all(&self, shapes: &[S]) -> Vec<f64> {
+        shapes.iter().map(|shape| self.evaluate_shape(shape)).collect()
+    }
+
+    fn evaluate_shape(&self, shape: &S) -> f64 {
+        let (min_x, max_x, min_y, max_y) = shape.pixel_bounds(self.width, self.height);
         if min_x >= max_x || min_y >= max_y { return f64::MAX; }
 
         let color = self.compute_optimal_color(shape);
         let (r, g, b, a) = color;
         let alpha_f = a as f64 / 255.0;
 
-        let cos = shape.angle.cos();
-        let sin = shape.angle.sin();
-        let rx2 = shape.rx * shape.rx;
-        let ry2 = shape.ry * shape.ry;
+        let weights = self.scoring_weights();
+        
+        if let Some(cache) = &self.perceptual {
+            let mut total_error_diff = 0.0;
+            shape.for_each_span(self.width, self.height, |y, start, end| {
+                for idx in self.span_indices(y, start, end) {
+                    let blend = |c: usize, s: u8| self.blender.blend(self.current_pixels[c], s, alpha_f);
+                    let new_color = cache.space.convert(blend(idx, r), blend(idx + 1, g), blend(idx + 2, b));
+                    let target = cache.target[idx / 4];
+                    let diff = squared_distance(target, new_color) - squared_distance(target, cache.current[idx / 4]);
+                    total_error_diff += weights.map_or(diff, |weights| diff * weights[idx / 4] as f64);
+                }
+            });
+            return total_error_diff;
+        }
 
         let mut total_error_diff = 0i64;
+        let mut weighted_error_diff = 0.0;
         
-        for y in min_y..max_y {
-            for x in min_x..max_x {
-                 let dx = x as f64 - shape.x;
-                let dy = y as f64 - shape.y;
-                let rot_x = dx * cos + dy * sin;
-                let rot_y = -dx * sin + dy * cos;
-                if (rot_x * rot_x) / rx2 + (rot_y * rot_y) / ry2 <= 1.0 {
-                    let idx = (y * self.width + x) as usize * 4;
-                    let tr = self.target_pixels[idx] as i32;
-                    let tg = self.target_pixels[idx+1] as i32;
-                    let tb = self.target_pixels[idx+2] as i32;
-                    
-                    let cr = self.current_pixels[idx] as i32;
-                    let cg = self.current_pixels[idx+1] as i32;
-                    let cb = self.current_pixels[idx+2] as i32;
-                    
-                    // Blend: New = Current*(1-a) + Shape*a
-                    let nr = (cr as f64 * (1.0 - alpha_f) + r as f64 * alpha_f) as i32;
-                    let ng = (cg as f64 * (1.0 - alpha_f) + g as f64 * alpha_f) as i32;
-                    let nb = (cb as f64 * (1.0 - alpha_f) + b as f64 * alpha_f) as i32;
-                    
-                    let old_err = (tr-cr).pow(2) + (tg-cg).pow(2) + (tb-cb).pow(2);
-                    let new_err = (tr-nr).pow(2) + (tg-ng).pow(2) + (tb-nb).pow(2);
-                    
-                    total_error_diff += new_err as i64 - old_err as i64;
+        shape.for_each_span(self.width, self.height, |y, start, end| {
+            for idx in self.span_indices(y, start, end) {
+                let tr = self.target_pixels[idx] as i32;
+                let tg = self.target_pixels[idx+1] as i32;
+                let tb = self.target_pixels[idx+2] as i32;
+                
+                let cr = self.current_pixels[idx] as i32;
+                let cg = self.current_pixels[idx+1] as i32;
+                let cb = self.current_pixels[idx+2] as i32;
+                
+                // Blend: New = Current*(1-a) + Shape*a
+                let nr = self.blender.blend(cr as u8, r, alpha_f) as i32;
+                let ng = self.blender.blend(cg as u8, g, alpha_f) as i32;
+                let nb = self.blender.blend(cb as u8, b, alpha_f) as i32;
+                
+                let old_err = (tr-cr).pow(2) + (tg-cg).pow(2) + (tb-cb).pow(2);
+                let new_err = (tr-nr).pow(2) + (tg-ng).pow(2) + (tb-nb).pow(2);
+                
+                let diff = new_err as i64 - old_err as i64;
+                match weights {
+                    Some(weights) => weighted_error_diff += diff as f64 * weights[idx / 4] as f64,
+                    None => total_error_diff += diff,
                 }
             }
-        }
-        total_error_diff as f64
+        });
+        total_error_diff as f64 + weighted_error_diff
     }
     
-    fn compute_optimal_color(&self, shape: &Ellipse) -> (u8, u8, u8, u8) {
-        let r_max = shape.rx.max(shape.ry);
-        let min_x = (shape.x - r_max).floor().max(0.0) as u32;
-        let max_x = (shape.x + r_max).ceil().min(self.width as f64) as u32;
-        let min_y = (shape.y - r_max).floor().max(0.0) as u32;
-        let max_y = (shape.y + r_max).ceil().min(self.height as f64) as u32;
-        
+    fn compute_optimal_color(&self, shape: &S) -> (u8, u8, u8, u8) {
+        if self.config.solve_alpha {
+            return self.solve_color_and_alpha(shape);
+        }
         let mut sum_r = 0i64;
         let mut sum_g = 0i64;
         let mut sum_b = 0i64;
         let mut count = 0u64;
-        let cos = shape.angle.cos();
-        let sin = shape.angle.sin();
-        let rx2 = shape.rx * shape.rx;
-        let ry2 = shape.ry * shape.ry;
         
-        let alpha = shape.alpha as f64 / 255.0;
-        if alpha < MIN_ALPHA_THRESHOLD {
+        let alpha = shape.alpha() as f64 / 255.0;
+        if alpha < self.config.min_alpha_threshold {
             return (0, 0, 0, 0);
         }
+        if self.blender.mode() == BlendMode::Linear {
+            return self.compute_optimal_linear_color(shape, alpha);
+        }
 
-        for y in min_y..max_y {
-            for x in min_x..max_x {
-                let dx = x as f64 - shape.x;
-                let dy = y as f64 - shape.y;
-                let rot_x = dx * cos + dy * sin;
-                let rot_y = -dx * sin + dy * cos;
-                if (rot_x * rot_x) / rx2 + (rot_y * rot_y) / ry2 <= 1.0 {
-                    let idx = (y * self.width + x) as usize * 4;
-                    
-                    let tr = self.target_pixels[idx] as f64;
-                    let tg = self.target_pixels[idx+1] as f64;
-                    let tb = self.target_pixels[idx+2] as f64;
-                    
-                    let cr = self.current_pixels[idx] as f64;
-                    let cg = self.current_pixels[idx+1] as f64;
-                    let cb = self.current_pixels[idx+2] as f64;
-                    
-                    let kr = (tr - cr * (1.0 - alpha)) / alpha;
-                    let kg = (tg - cg * (1.0 - alpha)) / alpha;
-                    let kb = (tb - cb * (1.0 - alpha)) / alpha;
-                    
-                    sum_r += kr.clamp(0.0, 255.0) as i64;
-                    sum_g += kg.clamp(0.0, 255.0) as i64;
-                    sum_b += kb.clamp(0.0, 255.0) as i64;
-                    
-                    count += 1;
-                }
+        shape.for_each_span(self.width, self.height, |y, start, end| {
+            for idx in self.span_indices(y, start, end) {
+                let tr = self.target_pixels[idx] as f64;
+                let tg = self.target_pixels[idx+1] as f64;
+                let tb = self.target_pixels[idx+2] as f64;
+                
+                let cr = self.current_pixels[idx] as f64;
+                let cg = self.current_pixels[idx+1] as f64;
+                let cb = self.current_pixels[idx+2] as f64;
+                
+                let kr = (tr - cr * (1.0 - alpha)) / alpha;
+                let kg = (tg - cg * (1.0 - alpha)) / alpha;
+                let kb = (tb - cb * (1.0 - alpha)) / alpha;
+                
+                sum_r += kr.clamp(0.0, 255.0) as i64;
+                sum_g += kg.clamp(0.0, 255.0) as i64;
+                sum_b += kb.clamp(0.0, 255.0) as i64;
             }
-        }
+            count += (end - start) as u64;
+        });
         
         if count == 0 {
-            return (128, 128, 128, shape.alpha);
+            return (128, 128, 128, shape.alpha());
         }
         
         ((sum_r as u64 / count) as u8, 
          (sum_g as u64 / count) as u8, 
          (sum_b as u64 / count) as u8, 
-         shape.alpha)
+         shape.alpha())
+    }
+
+    // Joint least-squares fit of color k and opacity a over the shape's pixels, where
+    // each pixel becomes c + a * (k - c). With u = a * k the residual is linear in
+    // (u, a), giving a = 1 - cov(c, t) / var(c) summed over channels and
+    // k = (mean(t) - (1 - a) * mean(c)) / a. If k has to be clamped into range, a is
+    // searched again with the color clamped for each candidate. Over a flat canvas
+    // any opacity fits equally well, so the shape's own alpha is kept.
+    fn solve_color_and_alpha(&self, shape: &S) -> (u8, u8, u8, u8) {
+        let linear = self.blender.mode() == BlendMode::Linear;
+        let value = |v: u8| if linear { self.blender.decode(v) } else { v as f64 };
+        let mut sum_c = [0.0f64; 3];
+        let mut sum_t = [0.0f64; 3];
+        let mut sum_cc = [0.0f64; 3];
+        let mut sum_ct = [0.0f64; 3];
+        let mut count = 0u64;
+        
+        shape.for_each_span(self.width, self.height, |y, start, end| {
+            for idx in self.span_indices(y, start, end) {
+                for ch in 0..3 {
+                    let c = value(self.current_pixels[idx + ch]);
+                    let t = value(self.target_pixels[idx + ch]);
+                    sum_c[ch] += c;
+                    sum_t[ch] += t;
+                    sum_cc[ch] += c * c;
+                    sum_ct[ch] += c * t;
+                }
+            }
+            count += (end - start) as u64;
+        });
+        
+        if count == 0 {
+            return (128, 128, 128, shape.alpha());
+        }
+        
+        let n = count as f64;
+        let peak = if linear { 1.0 } else { 255.0 };
+        let params = &self.config.mutation;
+        let quantize = |a: f64| (a * 255.0).round().clamp(params.min_alpha.max(1) as f64, params.max_alpha.max(1) as f64) as u8;
+        let color_for = |alpha: u8| {
+            let a = alpha as f64 / 255.0;
+            [0, 1, 2].map(|ch| (sum_t[ch] - (1.0 - a) * sum_c[ch]) / n / a)
+        };
+        
+        let variance: f64 = (0..3).map(|ch| sum_cc[ch] - sum_c[ch] * sum_c[ch] / n).sum();
+        let covariance: f64 = (0..3).map(|ch| sum_ct[ch] - sum_c[ch] * sum_t[ch] / n).sum();
+        let mut alpha = if variance > VARIANCE_EPSILON * peak * peak * n {
+            quantize(1.0 - covariance / variance)
+        } else {
+            shape.alpha().max(1)
+        };
+        
+        if color_for(alpha).iter().any(|&k| k < 0.0 || k > peak) {
+            // For a fixed alpha the clamped color is still the best one, and the error
+            // of painting it follows from the sums. Clamping u into [0, a * peak] keeps
+            // the problem convex, so the error is convex in alpha and a ternary search
+            // over the allowed values finds its minimum.
+            let error = |alpha: u8| {
+                let a = alpha as f64 / 255.0;
+                let k = color_for(alpha).map(|k| k.clamp(0.0, peak));
+                // Sum of (a * (k - c) - (t - c))^2, less the constant sum of (t - c)^2.
+                (0..3).map(|ch| {
+                    let spread = n * k[ch] * k[ch] - 2.0 * k[ch] * sum_c[ch] + sum_cc[ch];
+                    let overlap = k[ch] * (sum_t[ch] - sum_c[ch]) - sum_ct[ch] + sum_cc[ch];
+                    a * a * spread - 2.0 * a * overlap
+                }).sum::<f64>()
+            };
+            let (mut lo, mut hi) = (params.min_alpha.max(1), params.max_alpha.max(1));
+            while hi - lo > 2 {
+                let third = (hi - lo) / 3;
+                if error(lo + third) <= error(hi - third) {
+                    hi -= third;
+                } else {
+                    lo += third;
+                }
+            }
+            alpha = (lo..=hi).min_by(|&a, &b| error(a).total_cmp(&error(b))).unwrap_or(alpha);
+        }
+        let color = color_for(alpha);
+        
+        let [r, g, b] = color.map(|k| {
+            let k = k.clamp(0.0, peak);
+            if linear { self.blender.encode(k) } else { k.round() as u8 }
+        });
+        (r, g, b, alpha)
     }
 
-    fn draw_shape(&mut self, shape: &Ellipse) {
-        let r_max = shape.rx.max(shape.ry);
-        let min_x = (shape.x - r_max).floor().max(0.0) as u32;
-        let max_x = (shape.x + r_max).ceil().min(self.width as f64) as u32;
-        let min_y = (shape.y - r_max).floor().max(0.0) as u32;
-        let max_y = (shape.y + r_max).ceil().min(self.height as f64) as u32;
+    // Same per-pixel solve as `compute_optimal_color`, but in linear light.
+    fn compute_optimal_linear_color(&self, shape: &S, alpha: f64) -> (u8, u8, u8, u8) {
+        let mut sums = [0.0f64; 3];
+        let mut count = 0u64;
         
-        let cos = shape.angle.cos();
-        let sin = shape.angle.sin();
-        let rx2 = shape.rx * shape.rx;
-        let ry2 = shape.ry * shape.ry;
-        let (r, g, b, a) = shape.color;
+        shape.for_each_span(self.width, self.height, |y, start, end| {
+            for idx in self.span_indices(y, start, end) {
+                for (c, sum) in sums.iter_mut().enumerate() {
+                    let t = self.blender.decode(self.target_pixels[idx + c]);
+                    let cur = self.blender.decode(self.current_pixels[idx + c]);
+                    *sum += ((t - cur * (1.0 - alpha)) / alpha).clamp(0.0, 1.0);
+                }
+            }
+            count += (end - start) as u64;
+        });
+        
+        if count == 0 {
+            return (128, 128, 128, shape.alpha());
+        }
+        
+        let [r, g, b] = sums.map(|sum| self.blender.encode(sum / count as f64));
+        (r, g, b, shape.alpha())
+    }
+
+    fn draw_shape(&mut self, shape: &S) {
+        let (r, g, b, a) = shape.color();
         let alpha_f = a as f64 / 255.0;
+        let width = self.width;
+        let target = &self.target_pixels;
+        let current = &mut self.current_pixels;
+        let total_error = &mut self.total_error;
+        let perceptual = &mut self.perceptual;
+        let weights = &self.weights;
+        let blender = &self.blender;
 
-        for y in min_y..max_y {
-            for x in min_x..max_x {
-                let dx = x as f64 - shape.x;
-                let dy = y as f64 - shape.y;
-                let rot_x = dx * cos + dy * sin;
-                let rot_y = -dx * sin + dy * cos;
-                if (rot_x * rot_x) / rx2 + (rot_y * rot_y) / ry2 <= 1.0 {
-                    let idx = (y * self.width + x) as usize * 4;
-                    let cr = self.current_pixels[idx] as f64;
-                    let cg = self.current_pixels[idx+1] as f64;
-                    let cb = self.current_pixels[idx+2] as f64;
-                    
-                    self.current_pixels[idx] = (cr * (1.0 - alpha_f) + r as f64 * alpha_f) as u8;
-                    self.current_pixels[idx+1] = (cg * (1.0 - alpha_f) + g as f64 * alpha_f) as u8;
-                    self.current_pixels[idx+2] = (cb * (1.0 - alpha_f) + b as f64 * alpha_f) as u8;
-                    self.current_pixels[idx+3] = 255;
+        shape.for_each_span(self.width, self.height, |y, start, end| {
+            let row = (y * width) as usize * 4;
+            for idx in (row + start as usize * 4..row + end as usize * 4).step_by(4) {
+                let old_err = squared_error(&target[idx..idx+3], &current[idx..idx+3]);
+                
+                current[idx] = blender.blend(current[idx], r, alpha_f);
+                current[idx+1] = blender.blend(current[idx+1], g, alpha_f);
+                current[idx+2] = blender.blend(current[idx+2], b, alpha_f);
+                current[idx+3] = 255;
+                
+                let p = idx / 4;
+                let diff = match perceptual {
+                    Some(cache) => {
+                        let old_err = squared_distance(cache.target[p], cache.current[p]);
+                        cache.current[p] = cache.space.convert(current[idx], current[idx+1], current[idx+2]);
+                        squared_distance(cache.target[p], cache.current[p]) - old_err
+                    }
+                    None => (squared_error(&target[idx..idx+3], &current[idx..idx+3]) - old_err) as f64,
+                };
+                *total_error += weights.as_ref().map_or(diff, |weights| diff * weights[p] as f64);
+            }
+        });
+        self.refresh_tiles(shape.pixel_bounds(self.width, self.height));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::Ellipse;
+
+    fn ellipse(x: f64, y: f64, r: f64, color: (u8, u8, u8, u8)) -> Ellipse {
+        Ellipse { x, y, rx: r, ry: r, angle: 0.0, color, alpha: color.3 }
+    }
+
+    #[test]
+    fn prune_at_zero_removes_hidden_shapes() {
+        let (width, height) = (32, 32);
+        // A red disc on white, matched exactly by `cover`.
+        let target: Vec<u8> = (0..width * height)
+            .flat_map(|i| {
+                let (dx, dy) = ((i % width) as f64 + 0.5 - 16.0, (i / width) as f64 + 0.5 - 16.0);
+                if dx * dx + dy * dy <= 100.0 { [200, 20, 20, 255] } else { [255, 255, 255, 255] }
+            })
+            .collect();
+        let mut optimizer = Optimizer::<Ellipse>::new(&target, width, height, 1, OptimizerConfig::default());
+        let hidden = ellipse(16.0, 16.0, 4.0, (10, 200, 10, 128));
+        let cover = ellipse(16.0, 16.0, 10.0, (200, 20, 20, 255));
+        let mut shapes = vec![hidden, cover];
+        optimizer.resample(&target, width, height, None, &shapes);
+
+        let canvas = optimizer.current_pixels.clone();
+        assert_eq!(optimizer.prune(&mut shapes, 0.0, None), 1);
+        assert_eq!(shapes.len(), 1);
+        assert_eq!(shapes[0].rx, cover.rx);
+        assert_eq!(optimizer.current_pixels, canvas);
+    }
+
+    const SIZE: u32 = 16;
+
+    fn image(pixel: impl Fn(u32, u32) -> [u8; 3]) -> Vec<u8> {
+        (0..SIZE * SIZE).flat_map(|i| {
+            let [r, g, b] = pixel(i % SIZE, i / SIZE);
+            [r, g, b, 255]
+        }).collect()
+    }
+
+    // An optimizer whose canvas below the shape is `canvas`, with the solve for `shape`.
+    fn solve(target: &[u8], canvas: &[u8], shape: &Ellipse) -> (Optimizer<Ellipse>, (u8, u8, u8, u8)) {
+        let mut optimizer = Optimizer::<Ellipse>::new(target, SIZE, SIZE, 1, OptimizerConfig::default());
+        optimizer.current_pixels = canvas.to_vec();
+        let solved = optimizer.solve_color_and_alpha(shape);
+        (optimizer, solved)
+    }
+
+    // Squared error over the shape of painting `color` at `alpha` without rounding.
+    fn painted_error(optimizer: &Optimizer<Ellipse>, shape: &Ellipse, color: [f64; 3], alpha: u8) -> f64 {
+        let a = alpha as f64 / 255.0;
+        let mut error = 0.0;
+        shape.for_each_span(SIZE, SIZE, |y, start, end| {
+            for idx in optimizer.span_indices(y, start, end) {
+                for (ch, k) in color.iter().enumerate() {
+                    let c = optimizer.current_pixels[idx + ch] as f64;
+                    let t = optimizer.target_pixels[idx + ch] as f64;
+                    error += (c + a * (k - c) - t).powi(2);
                 }
             }
+        });
+        error
+    }
+
+    // The lowest error over every allowed alpha, each with its best clamped color.
+    // Per alpha, each channel's error is a parabola in the color, so its minimum is
+    // the mean of the per-pixel exact colors, clamped.
+    fn brute_force_error(optimizer: &Optimizer<Ellipse>, shape: &Ellipse) -> f64 {
+        let params = &optimizer.config.mutation;
+        (params.min_alpha..=params.max_alpha).map(|alpha| {
+            let a = alpha as f64 / 255.0;
+            let mut sums = [0.0; 3];
+            let mut count = 0.0;
+            shape.for_each_span(SIZE, SIZE, |y, start, end| {
+                for idx in optimizer.span_indices(y, start, end) {
+                    for (ch, sum) in sums.iter_mut().enumerate() {
+                        let c = optimizer.current_pixels[idx + ch] as f64;
+                        *sum += (optimizer.target_pixels[idx + ch] as f64 - (1.0 - a) * c) / a;
+                    }
+                    count += 1.0;
+                }
+            });
+            painted_error(optimizer, shape, sums.map(|sum| (sum / count).clamp(0.0, 255.0)), alpha)
+        }).fold(f64::INFINITY, f64::min)
+    }
+
+    fn solved_error(optimizer: &Optimizer<Ellipse>, shape: &Ellipse, (r, g, b, a): (u8, u8, u8, u8)) -> f64 {
+        painted_error(optimizer, shape, [r, g, b].map(f64::from), a)
+    }
+
+    #[test]
+    fn solve_recovers_an_exact_color_and_alpha() {
+        let shape = ellipse(8.0, 8.0, 6.0, (0, 0, 0, 128));
+        let canvas = image(|x, y| [(x * 16) as u8, (y * 16) as u8, ((x + y) * 8) as u8]);
+        let (color, a) = ([200.0, 50.0, 120.0], 0.4);
+        let target = image(|x, y| {
+            let c = &canvas[((y * SIZE + x) * 4) as usize..];
+            [0, 1, 2].map(|ch| (c[ch] as f64 + a * (color[ch] - c[ch] as f64)).round() as u8)
+        });
+        let (_, (r, g, b, alpha)) = solve(&target, &canvas, &shape);
+        assert!((alpha as f64 - a * 255.0).abs() <= 2.0, "alpha {}", alpha);
+        for (solved, expected) in [r, g, b].into_iter().zip(color) {
+            assert!((solved as f64 - expected).abs() <= 3.0, "color {:?}", (r, g, b));
+        }
+    }
+
+    #[test]
+    fn solve_matches_a_brute_force_alpha_sweep() {
+        let shape = ellipse(8.0, 8.0, 6.0, (0, 0, 0, 128));
+        let cases = [
+            // Unconstrained optimum inside the color range.
+            (image(|x, y| [(x * 15) as u8, 90, (y * 15) as u8]), image(|x, y| [((x * y) % 255) as u8, (y * 12) as u8, 200])),
+            // Best unconstrained fit is alpha 0.6 towards a color below black, so the
+            // color clamps and alpha has to be searched again.
+            (image(|x, y| [(0.4 * (100 + x * 9) as f64).max(60.0) as u8 - 60, (0.4 * (100 + y * 9) as f64) as u8 - 30, 10]), image(|x, y| [(100 + x * 9) as u8, (100 + y * 9) as u8, 180])),
+            // The same towards a color above white.
+            (image(|x, y| [(0.4 * (x * 9) as f64 + 200.0) as u8, (0.4 * (y * 9) as f64 + 180.0) as u8, 250]), image(|x, y| [(x * 9) as u8, (y * 9) as u8, 40])),
+        ];
+        for (target, canvas) in cases {
+            let (optimizer, solved) = solve(&target, &canvas, &shape);
+            let (error, best) = (solved_error(&optimizer, &shape, solved), brute_force_error(&optimizer, &shape));
+            // Allow for rounding the color to whole values.
+            let pixels = (std::f64::consts::PI * 36.0).ceil();
+            assert!(error <= best * 1.01 + pixels, "solve {:?} error {} vs brute force {}", solved, error, best);
         }
     }
+
+    #[test]
+    fn solve_keeps_alpha_on_a_uniform_canvas() {
+        // No canvas variance: any alpha fits equally well with the matching color.
+        let shape = ellipse(8.0, 8.0, 6.0, (0, 0, 0, 100));
+        let canvas = image(|_, _| [40, 80, 120]);
+        let target = image(|x, _| [120, 80, (x * 10) as u8]);
+        let (optimizer, solved) = solve(&target, &canvas, &shape);
+        assert_eq!(solved.3, 100);
+        assert!(solved_error(&optimizer, &shape, solved) <= brute_force_error(&optimizer, &shape) * 1.01 + 120.0);
+    }
+
+    #[test]
+    fn solve_clamps_alpha_to_the_configured_bounds() {
+        let shape = ellipse(8.0, 8.0, 6.0, (0, 0, 0, 128));
+        let canvas = image(|x, y| [(x * 16) as u8, (y * 16) as u8, 60]);
+        let params = OptimizerConfig::default().mutation;
+
+        // A flat target over a varied canvas needs full opacity.
+        let (_, (r, g, b, alpha)) = solve(&image(|_, _| [30, 160, 90]), &canvas, &shape);
+        assert_eq!((r, g, b, alpha), (30, 160, 90, params.max_alpha));
+
+        // A target equal to the canvas wants the shape invisible.
+        let (_, solved) = solve(&canvas, &canvas, &shape);
+        assert_eq!(solved.3, params.min_alpha);
+    }
 }
@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+const BLUR_RADIUS: i32 = 2;
+const NORMALIZE_PERCENTILE: f64 = 0.95;
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AutoImportance {
+    #[default]
+    None,
+    Edges,
+}
+
+fn luma(pixels: &[u8], width: u32, height: u32) -> Vec<f32> {
+    (0..(width * height) as usize)
+        .map(|i| 0.299 * pixels[i * 4] as f32 + 0.587 * pixels[i * 4 + 1] as f32 + 0.114 * pixels[i * 4 + 2] as f32)
+        .collect()
+}
+
+fn sobel(luma: &[f32], width: u32, height: u32) -> Vec<f32> {
+    let (w, h) = (width as i32, height as i32);
+    let at = |x: i32, y: i32| luma[(y.clamp(0, h - 1) * w + x.clamp(0, w - 1)) as usize];
+    let mut magnitude = vec![0.0; luma.len()];
+    for y in 0..h {
+        for x in 0..w {
+            let gx = at(x + 1, y - 1) + 2.0 * at(x + 1, y) + at(x + 1, y + 1)
+                - at(x - 1, y - 1) - 2.0 * at(x - 1, y) - at(x - 1, y + 1);
+            let gy = at(x - 1, y + 1) + 2.0 * at(x, y + 1) + at(x + 1, y + 1)
+                - at(x - 1, y - 1) - 2.0 * at(x, y - 1) - at(x + 1, y - 1);
+            magnitude[(y * w + x) as usize] = (gx * gx + gy * gy).sqrt();
+        }
+    }
+    magnitude
+}
+
+fn box_blur(values: &[f32], width: u32, height: u32) -> Vec<f32> {
+    let (w, h) = (width as i32, height as i32);
+    let mut blurred = vec![0.0; values.len()];
+    for y in 0..h {
+        for x in 0..w {
+            let mut sum = 0.0;
+            let mut count = 0.0;
+            for sy in (y - BLUR_RADIUS).max(0)..=(y + BLUR_RADIUS).min(h - 1) {
+                for sx in (x - BLUR_RADIUS).max(0)..=(x + BLUR_RADIUS).min(w - 1) {
+                    sum += values[(sy * w + sx) as usize];
+                    count += 1.0;
+                }
+            }
+            blurred[(y * w + x) as usize] = sum / count;
+        }
+    }
+    blurred
+}
+
+// Gradient magnitude scaled to [floor, 1], falling off towards the borders by up to
+// `center_bias`.
+pub fn edge_importance(pixels: &[u8], width: u32, height: u32, center_bias: f64, floor: f64) -> Vec<f32> {
+    let edges = box_blur(&sobel(&luma(pixels, width, height), width, height), width, height);
+
+    let mut sorted = edges.clone();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let reference = sorted
+        .get(((sorted.len() as f64 * NORMALIZE_PERCENTILE) as usize).min(sorted.len().saturating_sub(1)))
+        .copied()
+        .unwrap_or(0.0)
+        .max(f32::EPSILON);
+
+    let (cx, cy) = (width as f64 / 2.0, height as f64 / 2.0);
+    edges
+        .iter()
+        .enumerate()
+        .map(|(i, &edge)| {
+            let x = (i as u32 % width) as f64;
+            let y = (i as u32 / width) as f64;
+            let dx = (x - cx) / cx.max(1.0);
+            let dy = (y - cy) / cy.max(1.0);
+            let center = 1.0 - center_bias * ((dx * dx + dy * dy) / 2.0).min(1.0);
+            let strength = (edge / reference).min(1.0) as f64 * center;
+            (floor + (1.0 - floor) * strength) as f32
+        })
+        .collect()
+}
@@ -1,15 +1,117 @@
 use rand::Rng;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::config::MutationConfig;
+use crate::desmos::{latex_list, list_precision, format_number, to_graph_point, to_graph_units, GraphEllipse};
 
-const MIN_ALPHA: u8 = 10;
-const MAX_ALPHA: u8 = 255;
 const DEFAULT_ALPHA: u8 = 128;
-const INITIAL_ALPHA_MIN: u8 = 10;
-const INITIAL_ALPHA_MAX: u8 = 200;
+const MIN_SEED_RADIUS: f64 = 0.5;
+
+// Uniform offset in (-range, range); a zero range freezes the parameter.
+fn jitter(range: f64, rng: &mut impl Rng) -> f64 {
+    if range > 0.0 { rng.gen_range(-range..range) } else { 0.0 }
+}
+
+// Alpha is the last `mutate` arm. A zero range drops it rather than wasting a step
+// on a no-op.
+fn mutates_alpha(params: &MutationConfig) -> bool {
+    params.alpha_range > 0.0
+}
+
+fn initial_radius(params: &MutationConfig, rng: &mut impl Rng) -> f64 {
+    if params.initial_max_size > 1.0 { rng.gen_range(1.0..params.initial_max_size) } else { 1.0 }
+}
+
+fn initial_alpha(params: &MutationConfig, rng: &mut impl Rng) -> u8 {
+    if params.initial_alpha_min < params.initial_alpha_max {
+        rng.gen_range(params.initial_alpha_min..params.initial_alpha_max)
+    } else {
+        params.initial_alpha_min
+    }
+}
 
-const POSITION_MUTATION_RANGE: f64 = 16.0;
-const RADIUS_MUTATION_RANGE: f64 = 8.0;
-const ANGLE_MUTATION_RANGE: f64 = 0.5;
-const ALPHA_MUTATION_RANGE: f64 = 30.0;
+// Seeds keep some size variety between half and all of the requested radius.
+fn seed_radius(radius: f64, rng: &mut impl Rng) -> f64 {
+    (rng.gen_range(0.5..=1.0) * radius).max(MIN_SEED_RADIUS)
+}
+
+pub trait Shape: Copy + Send + Sync {
+    fn new_random(w: u32, h: u32, params: &MutationConfig, rng: &mut impl Rng) -> Self;
+    // A random shape centered near (x, y), sized to roughly `radii` (horizontal, vertical).
+    fn new_seeded(x: f64, y: f64, radii: (f64, f64), w: u32, h: u32, params: &MutationConfig, rng: &mut impl Rng) -> Self;
+    fn mutate(&mut self, w: u32, h: u32, iteration: usize, max_iter: usize, params: &MutationConfig, rng: &mut impl Rng);
+    fn limit_radius(&mut self, max_radius: f64);
+    fn rescale(&mut self, factor: f64);
+    fn alpha(&self) -> u8;
+    fn color(&self) -> (u8, u8, u8, u8);
+    fn set_color(&mut self, color: (u8, u8, u8, u8));
+    fn pixel_bounds(&self, w: u32, h: u32) -> (u32, u32, u32, u32);
+    fn for_each_span(&self, w: u32, h: u32, f: impl FnMut(u32, u32, u32));
+    fn desmos_latex(&self, width: f64, height: f64) -> String;
+    fn desmos_list_definitions(shapes: &[Self], width: f64, height: f64) -> (Vec<String>, String);
+    fn svg_element(&self, width: f64, height: f64, fill: &str) -> String;
+}
+
+// Pixel bounds holding exactly the pixel centers in [min, max], so an edge landing
+// on a center keeps that pixel and a degenerate shape stays within its extent.
+fn clamp_bounds(min_x: f64, max_x: f64, min_y: f64, max_y: f64, w: u32, h: u32) -> (u32, u32, u32, u32) {
+    (
+        min_x.ceil().max(0.0) as u32,
+        (max_x.floor() + 1.0).min(w as f64) as u32,
+        min_y.ceil().max(0.0) as u32,
+        (max_y.floor() + 1.0).min(h as f64) as u32,
+    )
+}
+
+// Maps a pixel coordinate onto an image resized by `factor`, keeping pixel centers aligned.
+fn rescale_coord(value: f64, factor: f64) -> f64 {
+    (value + 0.5) * factor - 0.5
+}
+
+// Turns an approximate covered interval [lo, hi] on one row into the exact run of
+// pixels accepted by `inside`, so spans match a per-pixel test bit for bit.
+fn exact_span(lo: f64, hi: f64, min_x: u32, max_x: u32, inside: impl Fn(u32) -> bool) -> Option<(u32, u32)> {
+    if min_x >= max_x || lo.is_nan() || hi.is_nan() || lo > hi {
+        return None;
+    }
+    let clamp = |v: f64| v.max(min_x as f64).min((max_x - 1) as f64) as u32;
+    let mut start = clamp(lo.ceil());
+    let mut end = clamp(hi.floor());
+    if start > end {
+        if inside(start) {
+            end = start;
+        } else if inside(end) {
+            start = end;
+        } else {
+            return None;
+        }
+    }
+    while start <= end && !inside(start) {
+        start += 1;
+    }
+    while end > start && !inside(end) {
+        end -= 1;
+    }
+    if start > end {
+        return None;
+    }
+    while start > min_x && inside(start - 1) {
+        start -= 1;
+    }
+    while end + 1 < max_x && inside(end + 1) {
+        end += 1;
+    }
+    Some((start, end + 1))
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ShapeKind {
+    #[default]
+    Ellipse,
+    Triangle,
+}
 
 #[derive(Clone, Copy, Debug)]
 pub struct Ellipse {
@@ -22,36 +124,405 @@ pub struct Ellipse {
     pub alpha: u8,
 }
 
-impl Ellipse {
-    pub fn new_random(w: u32, h: u32) -> Self {
-        let mut rng = rand::thread_rng();
+impl Shape for Ellipse {
+    fn new_random(w: u32, h: u32, params: &MutationConfig, rng: &mut impl Rng) -> Self {
         Ellipse {
             x: rng.gen_range(0.0..w as f64),
             y: rng.gen_range(0.0..h as f64),
-            rx: rng.gen_range(1.0..32.0),
-            ry: rng.gen_range(1.0..32.0),
+            rx: initial_radius(params, rng),
+            ry: initial_radius(params, rng),
             angle: rng.gen_range(0.0..std::f64::consts::PI),
             color: (0, 0, 0, DEFAULT_ALPHA),
-            alpha: rng.gen_range(INITIAL_ALPHA_MIN..INITIAL_ALPHA_MAX),
+            alpha: initial_alpha(params, rng),
         }
     }
 
-    pub fn mutate(&mut self, w: u32, h: u32, iteration: usize, max_iter: usize) {
-        let mut rng = rand::thread_rng();
+    fn new_seeded(x: f64, y: f64, radii: (f64, f64), w: u32, h: u32, params: &MutationConfig, rng: &mut impl Rng) -> Self {
+        let mut s = Ellipse::new_random(w, h, params, rng);
+        s.x = x;
+        s.y = y;
+        s.rx = seed_radius(radii.0, rng);
+        s.ry = seed_radius(radii.1, rng);
+        s
+    }
+
+    fn mutate(&mut self, w: u32, h: u32, iteration: usize, max_iter: usize, params: &MutationConfig, rng: &mut impl Rng) {
         let progress = iteration as f64 / max_iter as f64;
         let scale = 1.0 - progress.powf(0.5);
         
-        match rng.gen_range(0..6) {
-            0 => self.x = (self.x + rng.gen_range(-POSITION_MUTATION_RANGE..POSITION_MUTATION_RANGE) * scale).clamp(0.0, w as f64),
-            1 => self.y = (self.y + rng.gen_range(-POSITION_MUTATION_RANGE..POSITION_MUTATION_RANGE) * scale).clamp(0.0, h as f64),
-            2 => self.rx = (self.rx + rng.gen_range(-RADIUS_MUTATION_RANGE..RADIUS_MUTATION_RANGE) * scale).clamp(0.5, w as f64),
-            3 => self.ry = (self.ry + rng.gen_range(-RADIUS_MUTATION_RANGE..RADIUS_MUTATION_RANGE) * scale).clamp(0.5, h as f64),
-            4 => self.angle = self.angle + rng.gen_range(-ANGLE_MUTATION_RANGE..ANGLE_MUTATION_RANGE) * scale,
+        match rng.gen_range(0..if mutates_alpha(params) { 6 } else { 5 }) {
+            0 => self.x = (self.x + jitter(params.position_range, rng) * scale).clamp(0.0, w as f64),
+            1 => self.y = (self.y + jitter(params.position_range, rng) * scale).clamp(0.0, h as f64),
+            2 => self.rx = (self.rx + jitter(params.radius_range, rng) * scale).clamp(0.5, w as f64),
+            3 => self.ry = (self.ry + jitter(params.radius_range, rng) * scale).clamp(0.5, h as f64),
+            4 => self.angle += jitter(params.angle_range, rng) * scale,
             5 => {
-                let delta = (rng.gen_range(-ALPHA_MUTATION_RANGE..ALPHA_MUTATION_RANGE) * scale) as i32;
-                self.alpha = (self.alpha as i32 + delta).clamp(MIN_ALPHA as i32, MAX_ALPHA as i32) as u8;
+                let delta = (jitter(params.alpha_range, rng) * scale) as i32;
+                self.alpha = (self.alpha as i32 + delta).clamp(params.min_alpha as i32, params.max_alpha as i32) as u8;
             }
             _ => {},
         }
     }
+
+    fn limit_radius(&mut self, max_radius: f64) {
+        self.rx = self.rx.min(max_radius);
+        self.ry = self.ry.min(max_radius);
+    }
+
+    fn rescale(&mut self, factor: f64) {
+        self.x = rescale_coord(self.x, factor);
+        self.y = rescale_coord(self.y, factor);
+        self.rx *= factor;
+        self.ry *= factor;
+    }
+
+    fn alpha(&self) -> u8 {
+        self.alpha
+    }
+
+    fn color(&self) -> (u8, u8, u8, u8) {
+        self.color
+    }
+
+    fn set_color(&mut self, color: (u8, u8, u8, u8)) {
+        self.color = color;
+    }
+
+    fn pixel_bounds(&self, w: u32, h: u32) -> (u32, u32, u32, u32) {
+        let r_max = self.rx.max(self.ry);
+        clamp_bounds(self.x - r_max, self.x + r_max, self.y - r_max, self.y + r_max, w, h)
+    }
+
+    fn for_each_span(&self, w: u32, h: u32, mut f: impl FnMut(u32, u32, u32)) {
+        let (min_x, max_x, min_y, max_y) = self.pixel_bounds(w, h);
+        let cos = self.angle.cos();
+        let sin = self.angle.sin();
+        let rx2 = self.rx * self.rx;
+        let ry2 = self.ry * self.ry;
+        
+        // Row y solves a*dx^2 + b*dx + c <= 0 for dx = x - self.x
+        let a = cos * cos / rx2 + sin * sin / ry2;
+        let b_per_dy = 2.0 * cos * sin * (1.0 / rx2 - 1.0 / ry2);
+        let c_per_dy2 = sin * sin / rx2 + cos * cos / ry2;
+        
+        for y in min_y..max_y {
+            let dy = y as f64 - self.y;
+            let b = b_per_dy * dy;
+            let c = c_per_dy2 * dy * dy - 1.0;
+            let root = (b * b - 4.0 * a * c).max(0.0).sqrt();
+            let lo = self.x + (-b - root) / (2.0 * a);
+            let hi = self.x + (-b + root) / (2.0 * a);
+            
+            let inside = |x: u32| {
+                let dx = x as f64 - self.x;
+                let rot_x = dx * cos + dy * sin;
+                let rot_y = -dx * sin + dy * cos;
+                (rot_x * rot_x) / rx2 + (rot_y * rot_y) / ry2 <= 1.0
+            };
+            if let Some((start, end)) = exact_span(lo, hi, min_x, max_x, inside) {
+                f(y, start, end);
+            }
+        }
+    }
+
+    fn desmos_latex(&self, width: f64, height: f64) -> String {
+        let GraphEllipse { cx, cy, rx, ry, rot } = to_graph_units(self, width, height);
+        
+        let cos = rot.cos();
+        let sin = rot.sin();
+        
+        format!(
+            r#"\frac{{\left(\left(x-{cx:.3}\right)\cdot{c:.3}+\left(y-{cy:.3}\right)\cdot{s:.3}\right)^{{2}}}}{{{rx:.3}^{{2}}}}+\frac{{\left(\left(x-{cx:.3}\right)\cdot{s:.3}-\left(y-{cy:.3}\right)\cdot{c:.3}\right)^{{2}}}}{{{ry:.3}^{{2}}}}\le1"#,
+            cx=cx, cy=cy, c=cos, s=sin, rx=rx, ry=ry
+        )
+    }
+
+    fn desmos_list_definitions(shapes: &[Self], width: f64, height: f64) -> (Vec<String>, String) {
+        let graph: Vec<GraphEllipse> = shapes.iter().map(|s| to_graph_units(s, width, height)).collect();
+        let precision = list_precision(height);
+        
+        let definitions = vec![
+            format!("X={}", latex_list(graph.iter().map(|g| format_number(g.cx, precision)))),
+            format!("Y={}", latex_list(graph.iter().map(|g| format_number(g.cy, precision)))),
+            format!("R_{{x}}={}", latex_list(graph.iter().map(|g| format_number(g.rx, precision)))),
+            format!("R_{{y}}={}", latex_list(graph.iter().map(|g| format_number(g.ry, precision)))),
+            format!("A={}", latex_list(graph.iter().map(|g| format_number(g.rot, precision)))),
+        ];
+        
+        let latex = r"\frac{\left(\left(x-X\right)\cos\left(A\right)+\left(y-Y\right)\sin\left(A\right)\right)^{2}}{R_{x}^{2}}+\frac{\left(\left(x-X\right)\sin\left(A\right)-\left(y-Y\right)\cos\left(A\right)\right)^{2}}{R_{y}^{2}}\le1".to_string();
+        (definitions, latex)
+    }
+
+    fn svg_element(&self, width: f64, height: f64, fill: &str) -> String {
+        let GraphEllipse { cx, cy, rx, ry, rot } = to_graph_units(self, width, height);
+        format!(
+            r#"<ellipse cx="{cx:.3}" cy="{cy:.3}" rx="{rx:.3}" ry="{ry:.3}" transform="rotate({deg:.3} {cx:.3} {cy:.3})" {fill}/>"#,
+            cx=cx, cy=cy, rx=rx, ry=ry, deg=rot.to_degrees(), fill=fill
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Triangle {
+    pub points: [(f64, f64); 3],
+    pub color: (u8, u8, u8, u8),
+    pub alpha: u8,
+}
+
+impl Triangle {
+    pub fn centroid(&self) -> (f64, f64) {
+        let [(x1, y1), (x2, y2), (x3, y3)] = self.points;
+        ((x1 + x2 + x3) / 3.0, (y1 + y2 + y3) / 3.0)
+    }
+
+    fn graph_points(&self, width: f64, height: f64) -> [(f64, f64); 3] {
+        self.points.map(|(x, y)| to_graph_point(x, y, width, height))
+    }
+}
+
+impl Shape for Triangle {
+    fn new_random(w: u32, h: u32, params: &MutationConfig, rng: &mut impl Rng) -> Self {
+        let cx = rng.gen_range(0.0..w as f64);
+        let cy = rng.gen_range(0.0..h as f64);
+        let mut points = [(0.0, 0.0); 3];
+        for p in points.iter_mut() {
+            *p = (cx + jitter(params.initial_max_size, rng), cy + jitter(params.initial_max_size, rng));
+        }
+        Triangle {
+            points,
+            color: (0, 0, 0, DEFAULT_ALPHA),
+            alpha: initial_alpha(params, rng),
+        }
+    }
+
+    fn new_seeded(x: f64, y: f64, radii: (f64, f64), w: u32, h: u32, params: &MutationConfig, rng: &mut impl Rng) -> Self {
+        let mut s = Triangle::new_random(w, h, params, rng);
+        let (cx, cy) = s.centroid();
+        for p in s.points.iter_mut() {
+            *p = (p.0 - cx + x, p.1 - cy + y);
+        }
+        s.limit_radius(seed_radius(radii.0.max(radii.1), rng));
+        s
+    }
+
+    fn mutate(&mut self, w: u32, h: u32, iteration: usize, max_iter: usize, params: &MutationConfig, rng: &mut impl Rng) {
+        let progress = iteration as f64 / max_iter as f64;
+        let scale = 1.0 - progress.powf(0.5);
+        
+        match rng.gen_range(0..if mutates_alpha(params) { 5 } else { 4 }) {
+            i @ 0..=2 => {
+                let (x, y) = self.points[i];
+                self.points[i] = (
+                    (x + jitter(params.position_range, rng) * scale).clamp(0.0, w as f64),
+                    (y + jitter(params.position_range, rng) * scale).clamp(0.0, h as f64),
+                );
+            }
+            3 => {
+                let dx = jitter(params.position_range, rng) * scale;
+                let dy = jitter(params.position_range, rng) * scale;
+                for p in self.points.iter_mut() {
+                    *p = (p.0 + dx, p.1 + dy);
+                }
+            }
+            4 => {
+                let delta = (jitter(params.alpha_range, rng) * scale) as i32;
+                self.alpha = (self.alpha as i32 + delta).clamp(params.min_alpha as i32, params.max_alpha as i32) as u8;
+            }
+            _ => {},
+        }
+    }
+
+    fn limit_radius(&mut self, max_radius: f64) {
+        let (cx, cy) = self.centroid();
+        for p in self.points.iter_mut() {
+            let (dx, dy) = (p.0 - cx, p.1 - cy);
+            let dist = (dx * dx + dy * dy).sqrt();
+            if dist > max_radius {
+                let k = max_radius / dist;
+                *p = (cx + dx * k, cy + dy * k);
+            }
+        }
+    }
+
+    fn rescale(&mut self, factor: f64) {
+        for p in self.points.iter_mut() {
+            *p = (rescale_coord(p.0, factor), rescale_coord(p.1, factor));
+        }
+    }
+
+    fn alpha(&self) -> u8 {
+        self.alpha
+    }
+
+    fn color(&self) -> (u8, u8, u8, u8) {
+        self.color
+    }
+
+    fn set_color(&mut self, color: (u8, u8, u8, u8)) {
+        self.color = color;
+    }
+
+    fn pixel_bounds(&self, w: u32, h: u32) -> (u32, u32, u32, u32) {
+        let xs = self.points.map(|p| p.0);
+        let ys = self.points.map(|p| p.1);
+        clamp_bounds(
+            xs.iter().cloned().fold(f64::MAX, f64::min),
+            xs.iter().cloned().fold(f64::MIN, f64::max),
+            ys.iter().cloned().fold(f64::MAX, f64::min),
+            ys.iter().cloned().fold(f64::MIN, f64::max),
+            w,
+            h,
+        )
+    }
+
+    fn for_each_span(&self, w: u32, h: u32, mut f: impl FnMut(u32, u32, u32)) {
+        let (min_x, max_x, min_y, max_y) = self.pixel_bounds(w, h);
+        let [(x1, y1), (x2, y2), (x3, y3)] = self.points;
+        let edge = |ax: f64, ay: f64, bx: f64, by: f64, px: f64, py: f64| (bx - ax) * (py - ay) - (by - ay) * (px - ax);
+        for y in min_y..max_y {
+            let py = y as f64;
+            let mut lo = f64::INFINITY;
+            let mut hi = f64::NEG_INFINITY;
+            for ((ax, ay), (bx, by)) in [((x1, y1), (x2, y2)), ((x2, y2), (x3, y3)), ((x3, y3), (x1, y1))] {
+                if py < ay.min(by) || py > ay.max(by) {
+                    continue;
+                }
+                let (from, to) = if ay == by { (ax.min(bx), ax.max(bx)) } else {
+                    let x = ax + (py - ay) * (bx - ax) / (by - ay);
+                    (x, x)
+                };
+                lo = lo.min(from);
+                hi = hi.max(to);
+            }
+            
+            let inside = |x: u32| {
+                let px = x as f64;
+                let e1 = edge(x1, y1, x2, y2, px, py);
+                let e2 = edge(x2, y2, x3, y3, px, py);
+                let e3 = edge(x3, y3, x1, y1, px, py);
+                (e1 >= 0.0 && e2 >= 0.0 && e3 >= 0.0) || (e1 <= 0.0 && e2 <= 0.0 && e3 <= 0.0)
+            };
+            if let Some((start, end)) = exact_span(lo, hi, min_x, max_x, inside) {
+                f(y, start, end);
+            }
+        }
+    }
+
+    fn desmos_latex(&self, width: f64, height: f64) -> String {
+        let [(x1, y1), (x2, y2), (x3, y3)] = self.graph_points(width, height);
+        format!(
+            r#"\operatorname{{polygon}}\left(\left({x1:.3},{y1:.3}\right),\left({x2:.3},{y2:.3}\right),\left({x3:.3},{y3:.3}\right)\right)"#,
+            x1=x1, y1=y1, x2=x2, y2=y2, x3=x3, y3=y3
+        )
+    }
+
+    fn desmos_list_definitions(shapes: &[Self], width: f64, height: f64) -> (Vec<String>, String) {
+        let graph: Vec<[(f64, f64); 3]> = shapes.iter().map(|s| s.graph_points(width, height)).collect();
+        let precision = list_precision(height);
+        
+        let mut definitions = Vec::new();
+        for i in 0..3 {
+            definitions.push(format!("X_{{{}}}={}", i + 1, latex_list(graph.iter().map(|g| format_number(g[i].0, precision)))));
+            definitions.push(format!("Y_{{{}}}={}", i + 1, latex_list(graph.iter().map(|g| format_number(g[i].1, precision)))));
+        }
+        
+        let latex = r"\operatorname{polygon}\left(\left(X_{1},Y_{1}\right),\left(X_{2},Y_{2}\right),\left(X_{3},Y_{3}\right)\right)".to_string();
+        (definitions, latex)
+    }
+
+    fn svg_element(&self, width: f64, height: f64, fill: &str) -> String {
+        let points: Vec<String> = self.graph_points(width, height).iter().map(|(x, y)| format!("{:.3},{:.3}", x, y)).collect();
+        format!(r#"<polygon points="{}" {}/>"#, points.join(" "), fill)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    const WIDTH: u32 = 64;
+    const HEIGHT: u32 = 48;
+
+    // Pixels covered by the spans, checking each row is reported at most once.
+    fn span_mask(shape: &impl Shape) -> Vec<bool> {
+        let mut mask = vec![false; (WIDTH * HEIGHT) as usize];
+        let mut rows = Vec::new();
+        shape.for_each_span(WIDTH, HEIGHT, |y, start, end| {
+            assert!(start < end && end <= WIDTH && y < HEIGHT, "bad span {y}: {start}..{end}");
+            rows.push(y);
+            for x in start..end {
+                mask[(y * WIDTH + x) as usize] = true;
+            }
+        });
+        let count = rows.len();
+        rows.dedup();
+        assert_eq!(rows.len(), count, "row reported twice");
+        mask
+    }
+
+    fn brute_force_mask(inside: impl Fn(f64, f64) -> bool) -> Vec<bool> {
+        (0..WIDTH * HEIGHT).map(|i| inside((i % WIDTH) as f64, (i / WIDTH) as f64)).collect()
+    }
+
+    fn ellipse_inside(e: &Ellipse, x: f64, y: f64) -> bool {
+        let (dx, dy) = (x - e.x, y - e.y);
+        let rot_x = dx * e.angle.cos() + dy * e.angle.sin();
+        let rot_y = -dx * e.angle.sin() + dy * e.angle.cos();
+        (rot_x * rot_x) / (e.rx * e.rx) + (rot_y * rot_y) / (e.ry * e.ry) <= 1.0
+    }
+
+    fn triangle_inside(t: &Triangle, x: f64, y: f64) -> bool {
+        let [(x1, y1), (x2, y2), (x3, y3)] = t.points;
+        let edge = |ax: f64, ay: f64, bx: f64, by: f64| (bx - ax) * (y - ay) - (by - ay) * (x - ax);
+        let (e1, e2, e3) = (edge(x1, y1, x2, y2), edge(x2, y2, x3, y3), edge(x3, y3, x1, y1));
+        // With two coincident corners the signs agree along the whole line, so also
+        // require the point to lie within the corners' extent.
+        let within = |v: f64, [a, b, c]: [f64; 3]| a.min(b).min(c) <= v && v <= a.max(b).max(c);
+        within(x, [x1, x2, x3]) && within(y, [y1, y2, y3])
+            && ((e1 >= 0.0 && e2 >= 0.0 && e3 >= 0.0) || (e1 <= 0.0 && e2 <= 0.0 && e3 <= 0.0))
+    }
+
+    // Coordinates that reach past the canvas, snapped to whole pixels half the time
+    // so boundaries land exactly on pixel centers.
+    fn coordinate(rng: &mut StdRng, extent: u32) -> f64 {
+        let v = rng.gen_range(-20.0..extent as f64 + 20.0);
+        if rng.gen_bool(0.5) { v.round() } else { v }
+    }
+
+    #[test]
+    fn ellipse_spans_match_per_pixel_test() {
+        let mut rng = StdRng::seed_from_u64(12);
+        for i in 0..2000 {
+            let (rx, ry) = match i % 3 {
+                0 => (rng.gen_range(0.5..40.0), rng.gen_range(0.5..40.0)),
+                1 => (rng.gen_range(5.0..40.0), rng.gen_range(0.05..1.0)),
+                _ => (rng.gen_range(1.0..6.0f64).round(), rng.gen_range(1.0..6.0f64).round()),
+            };
+            let angle = if i % 4 == 0 { 0.0 } else { rng.gen_range(-4.0..4.0) };
+            let e = Ellipse { x: coordinate(&mut rng, WIDTH), y: coordinate(&mut rng, HEIGHT), rx, ry, angle, color: (0, 0, 0, 255), alpha: 255 };
+            assert_eq!(span_mask(&e), brute_force_mask(|x, y| ellipse_inside(&e, x, y)), "{:?}", e);
+        }
+    }
+
+    #[test]
+    fn triangle_spans_match_per_pixel_test() {
+        let mut rng = StdRng::seed_from_u64(12);
+        for i in 0..2000 {
+            let mut points = [(); 3].map(|_| (coordinate(&mut rng, WIDTH), coordinate(&mut rng, HEIGHT)));
+            match i % 4 {
+                // Collinear.
+                0 => points[2] = (2.0 * points[1].0 - points[0].0, 2.0 * points[1].1 - points[0].1),
+                // Two coincident corners.
+                1 => points[2] = points[0],
+                // Horizontal edge.
+                2 => points[1].1 = points[0].1,
+                _ => {}
+            }
+            let t = Triangle { points, color: (0, 0, 0, 255), alpha: 255 };
+            assert_eq!(span_mask(&t), brute_force_mask(|x, y| triangle_inside(&t, x, y)), "{:?}", t);
+        }
+    }
 }
@@ -0,0 +1,102 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+// Shapes over which the RMSE drop is averaged for the diminishing-returns check.
+const IMPROVEMENT_WINDOW: usize = 10;
+
+// Optional conditions that end a run before `max_shapes` is reached. Errors are
+// measured on the working canvas.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct StopCriteria {
+    pub target_rmse: Option<f64>,
+    pub target_psnr: Option<f64>,
+    pub min_improvement: Option<f64>,
+    pub time_budget_secs: Option<f64>,
+    pub max_evaluations: Option<u64>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StopReason {
+    ShapeLimit,
+    TargetRmse,
+    TargetPsnr,
+    DiminishingReturns,
+    TimeBudget,
+    EvaluationBudget,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::ShapeLimit => write!(f, "shape limit reached"),
+            StopReason::TargetRmse => write!(f, "target RMSE reached"),
+            StopReason::TargetPsnr => write!(f, "target PSNR reached"),
+            StopReason::DiminishingReturns => write!(f, "improvement per shape fell below the minimum"),
+            StopReason::TimeBudget => write!(f, "time budget used up"),
+            StopReason::EvaluationBudget => write!(f, "evaluation budget used up"),
+        }
+    }
+}
+
+pub(crate) struct Progress<'a> {
+    pub rmse: f64,
+    pub psnr: f64,
+    // RMSE after each shape placed at the current resolution.
+    pub history: &'a [f64],
+    pub evaluations: u64,
+    pub elapsed_secs: f64,
+}
+
+impl StopCriteria {
+    pub(crate) fn check(&self, progress: &Progress) -> Option<StopReason> {
+        if self.target_rmse.is_some_and(|target| progress.rmse <= target) {
+            return Some(StopReason::TargetRmse);
+        }
+        if self.target_psnr.is_some_and(|target| progress.psnr >= target) {
+            return Some(StopReason::TargetPsnr);
+        }
+        if let Some(min_improvement) = self.min_improvement {
+            let history = progress.history;
+            if history.len() > IMPROVEMENT_WINDOW {
+                let last = history.len() - 1;
+                let improvement = (history[last - IMPROVEMENT_WINDOW] - history[last]) / IMPROVEMENT_WINDOW as f64;
+                if improvement < min_improvement {
+                    return Some(StopReason::DiminishingReturns);
+                }
+            }
+        }
+        if self.time_budget_secs.is_some_and(|budget| progress.elapsed_secs >= budget) {
+            return Some(StopReason::TimeBudget);
+        }
+        if self.max_evaluations.is_some_and(|budget| progress.evaluations >= budget) {
+            return Some(StopReason::EvaluationBudget);
+        }
+        None
+    }
+}
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = performance, js_name = now)]
+    fn performance_now() -> f64;
+}
+
+// Seconds on a monotonic clock. Browsers have no `Instant`, so wasm uses
+// `performance.now()`, which unlike `Date.now()` ignores wall-clock changes.
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub(crate) fn now_secs() -> f64 {
+    performance_now() / 1000.0
+}
+
+#[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+pub(crate) fn now_secs() -> f64 {
+    static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    START.get_or_init(std::time::Instant::now).elapsed().as_secs_f64()
+}
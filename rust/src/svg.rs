@@ -0,0 +1,35 @@
+use std::fmt::Write;
+
+use crate::shapes::Shape;
+
+pub fn build_svg<S: Shape>(shapes: &[S], background: [u8; 3], img_width: u32, img_height: u32) -> String {
+    let width = img_width as f64;
+    let height = img_height as f64;
+    let aspect = width / height;
+    let xmax = 10.0 * aspect;
+    let xmin = -xmax;
+    let ymax = 10.0;
+    
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="{:.3} {:.3} {:.3} {:.3}">"#,
+        img_width, img_height, xmin, -ymax, xmax - xmin, 2.0 * ymax
+    );
+    let _ = writeln!(
+        svg,
+        r##"<rect x="{:.3}" y="{:.3}" width="{:.3}" height="{:.3}" fill="#{:02x}{:02x}{:02x}"/>"##,
+        xmin, -ymax, xmax - xmin, 2.0 * ymax, background[0], background[1], background[2]
+    );
+    
+    // Desmos graph units have y pointing up, so flip once and reuse the exported angles as-is.
+    svg.push_str("<g transform=\"scale(1,-1)\">\n");
+    for shape in shapes {
+        let (r, g, b, a) = shape.color();
+        let fill = format!(r##"fill="#{:02x}{:02x}{:02x}" fill-opacity="{:.3}""##, r, g, b, a as f64 / 255.0);
+        let _ = writeln!(svg, "{}", shape.svg_element(width, height, &fill));
+    }
+    svg.push_str("</g>\n</svg>\n");
+    
+    svg
+}
@@ -0,0 +1,200 @@
+use wasm_bindgen::prelude::*;
+
+use crate::desmos::OutputMode;
+use crate::shapes::{Ellipse, ShapeKind, Triangle};
+use crate::stop::StopReason;
+use crate::{image_ops, logging, Error, OptimizerConfig};
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = console)]
+    fn log(s: &str);
+}
+
+impl From<Error> for JsValue {
+    fn from(e: Error) -> Self {
+        JsValue::from_str(&e.to_string())
+    }
+}
+
+#[wasm_bindgen(start)]
+pub fn start() {
+    console_error_panic_hook::set_once();
+    logging::set_sink(log);
+}
+
+#[wasm_bindgen]
+pub fn greet() {
+    log("Rust initiated!");
+}
+
+#[wasm_bindgen]
+pub struct ImageProcessor {
+    inner: image_ops::ImageProcessor,
+}
+
+#[wasm_bindgen]
+impl ImageProcessor {
+    #[wasm_bindgen(constructor)]
+    pub fn new(image_data: &[u8]) -> Result<ImageProcessor, JsValue> {
+        Ok(ImageProcessor { inner: image_ops::ImageProcessor::new(image_data)? })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.inner.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.inner.height()
+    }
+
+    pub fn get_pixels(&self) -> Vec<u8> {
+        self.inner.get_pixels()
+    }
+}
+
+#[wasm_bindgen(js_name = FidelityMode)]
+pub struct Fidelity {
+    inner: crate::FidelityMode,
+}
+
+#[wasm_bindgen(js_class = FidelityMode)]
+impl Fidelity {
+    pub fn standard() -> Fidelity {
+        Fidelity { inner: crate::FidelityMode::Standard }
+    }
+
+    pub fn high() -> Fidelity {
+        Fidelity { inner: crate::FidelityMode::High }
+    }
+
+    #[wasm_bindgen(js_name = "super")]
+    pub fn super_fidelity() -> Fidelity {
+        Fidelity { inner: crate::FidelityMode::Super }
+    }
+
+    pub fn hyper() -> Fidelity {
+        Fidelity { inner: crate::FidelityMode::Hyper }
+    }
+
+    pub fn custom(trials: usize, steps: usize) -> Fidelity {
+        Fidelity { inner: crate::FidelityMode::Custom { trials, steps } }
+    }
+
+    pub fn parse(name: &str) -> Result<Fidelity, JsValue> {
+        Ok(Fidelity { inner: name.parse::<crate::FidelityMode>()? })
+    }
+
+    pub fn from_index(index: u8) -> Result<Fidelity, JsValue> {
+        Ok(Fidelity { inner: crate::FidelityMode::try_from(index)? })
+    }
+}
+
+enum AnyOptimizer {
+    Ellipse(crate::DesmosOptimizer<Ellipse>),
+    Triangle(crate::DesmosOptimizer<Triangle>),
+}
+
+macro_rules! dispatch {
+    ($self:expr, $inner:ident => $body:expr) => {
+        match $self {
+            AnyOptimizer::Ellipse($inner) => $body,
+            AnyOptimizer::Triangle($inner) => $body,
+        }
+    };
+}
+
+#[wasm_bindgen]
+pub struct DesmosOptimizer {
+    inner: AnyOptimizer,
+}
+
+#[wasm_bindgen]
+impl DesmosOptimizer {
+    #[wasm_bindgen(constructor)]
+    pub fn new(image_data: &[u8], max_shapes: usize, fidelity: &Fidelity, seed: u64, shape_kind: ShapeKind) -> Result<DesmosOptimizer, JsValue> {
+        let inner = match shape_kind {
+            ShapeKind::Ellipse => AnyOptimizer::Ellipse(crate::DesmosOptimizer::new(image_data, max_shapes, fidelity.inner, seed)?),
+            ShapeKind::Triangle => AnyOptimizer::Triangle(crate::DesmosOptimizer::new(image_data, max_shapes, fidelity.inner, seed)?),
+        };
+        Ok(DesmosOptimizer { inner })
+    }
+
+    pub fn get_config(&self) -> Result<JsValue, JsValue> {
+        Ok(dispatch!(&self.inner, o => serde_wasm_bindgen::to_value(o.config()))?)
+    }
+
+    pub fn set_config(&mut self, config: JsValue) -> Result<(), JsValue> {
+        let config: OptimizerConfig = serde_wasm_bindgen::from_value(config)
+            .map_err(|e| Error::Config(e.to_string()))?;
+        Ok(dispatch!(&mut self.inner, o => o.set_config(config))?)
+    }
+
+    pub fn set_weight_map(&mut self, image_data: &[u8]) -> Result<(), JsValue> {
+        Ok(dispatch!(&mut self.inner, o => o.set_weight_map(image_data))?)
+    }
+
+    pub fn add_weight_rect(&mut self, x: f64, y: f64, width: f64, height: f64, weight: f32) -> Result<(), JsValue> {
+        Ok(dispatch!(&mut self.inner, o => o.add_weight_rect(x, y, width, height, weight))?)
+    }
+
+    pub fn add_weight_ellipse(&mut self, cx: f64, cy: f64, rx: f64, ry: f64, weight: f32) -> Result<(), JsValue> {
+        Ok(dispatch!(&mut self.inner, o => o.add_weight_ellipse(cx, cy, rx, ry, weight))?)
+    }
+
+    pub fn clear_weights(&mut self) {
+        dispatch!(&mut self.inner, o => o.clear_weights())
+    }
+
+    pub fn step(&mut self, batch_size: usize) -> bool {
+        dispatch!(&mut self.inner, o => o.step(batch_size)).is_some()
+    }
+
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        dispatch!(&self.inner, o => o.stop_reason())
+    }
+
+    pub fn refine(&mut self, passes: usize) -> f64 {
+        dispatch!(&mut self.inner, o => o.refine(passes))
+    }
+
+    pub fn prune(&mut self, min_contribution: f64, target_count: Option<usize>) -> usize {
+        dispatch!(&mut self.inner, o => o.prune(min_contribution, target_count))
+    }
+
+    pub fn rmse(&self) -> f64 {
+        dispatch!(&self.inner, o => o.rmse())
+    }
+
+    pub fn psnr(&self) -> f64 {
+        dispatch!(&self.inner, o => o.psnr())
+    }
+
+    pub fn get_error_history(&self) -> Vec<f64> {
+        dispatch!(&self.inner, o => o.error_history().to_vec())
+    }
+
+    pub fn width(&self) -> u32 {
+        dispatch!(&self.inner, o => o.width())
+    }
+
+    pub fn height(&self) -> u32 {
+        dispatch!(&self.inner, o => o.height())
+    }
+
+    pub fn get_canvas_pixels(&self) -> Vec<u8> {
+        dispatch!(&self.inner, o => o.get_canvas_pixels())
+    }
+
+    pub fn render_png(&self) -> Result<Vec<u8>, JsValue> {
+        Ok(dispatch!(&self.inner, o => o.render_png())?)
+    }
+
+    pub fn get_json(&self, mode: OutputMode) -> Result<String, JsValue> {
+        Ok(dispatch!(&self.inner, o => o.get_json(mode))?)
+    }
+
+    pub fn get_svg(&self) -> String {
+        dispatch!(&self.inner, o => o.get_svg())
+    }
+}
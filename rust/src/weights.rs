@@ -0,0 +1,87 @@
+use image::imageops::FilterType;
+use image::{ImageBuffer, Luma};
+
+use crate::Error;
+
+const DEFAULT_WEIGHT: f32 = 1.0;
+// Painted weights are clamped to [0, MAX_WEIGHT] so error sums stay finite.
+pub const MAX_WEIGHT: f32 = 1000.0;
+
+pub struct WeightMap {
+    width: u32,
+    height: u32,
+    values: Vec<f32>,
+}
+
+impl WeightMap {
+    pub fn uniform(width: u32, height: u32) -> Self {
+        WeightMap { width, height, values: vec![DEFAULT_WEIGHT; (width * height) as usize] }
+    }
+
+    // Brightness becomes weight: white counts fully, black not at all. The mask is
+    // stretched to the image size if the dimensions differ.
+    pub fn from_image(image_data: &[u8], width: u32, height: u32) -> Result<Self, Error> {
+        let mask = image::load_from_memory(image_data)?.to_luma32f();
+        let mask = if mask.dimensions() == (width, height) {
+            mask
+        } else {
+            image::imageops::resize(&mask, width, height, FilterType::Triangle)
+        };
+        Ok(WeightMap { width, height, values: mask.into_raw() })
+    }
+
+    pub fn fill_rect(&mut self, x: f64, y: f64, w: f64, h: f64, weight: f32) -> Result<(), Error> {
+        self.fill(weight, |px, py| px >= x && px < x + w && py >= y && py < y + h)
+    }
+
+    pub fn fill_ellipse(&mut self, cx: f64, cy: f64, rx: f64, ry: f64, weight: f32) -> Result<(), Error> {
+        self.fill(weight, |px, py| {
+            let dx = (px - cx) / rx;
+            let dy = (py - cy) / ry;
+            dx * dx + dy * dy <= 1.0
+        })
+    }
+
+    fn fill(&mut self, weight: f32, inside: impl Fn(f64, f64) -> bool) -> Result<(), Error> {
+        if !weight.is_finite() {
+            return Err(Error::InvalidWeight(weight));
+        }
+        let weight = weight.clamp(0.0, MAX_WEIGHT);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if inside(x as f64, y as f64) {
+                    self.values[(y * self.width + x) as usize] = weight;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn resized(&self, width: u32, height: u32) -> Vec<f32> {
+        if (width, height) == (self.width, self.height) {
+            return self.values.clone();
+        }
+        let map = ImageBuffer::<Luma<f32>, _>::from_raw(self.width, self.height, self.values.clone())
+            .expect("weight buffer does not match its dimensions");
+        image::imageops::resize(&map, width, height, FilterType::Triangle).into_raw()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fill_rejects_non_finite_and_clamps_large_weights() {
+        let mut map = WeightMap::uniform(4, 4);
+        for weight in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            assert!(matches!(map.fill_rect(0.0, 0.0, 2.0, 2.0, weight), Err(Error::InvalidWeight(_))));
+        }
+        assert_eq!(map.values, vec![DEFAULT_WEIGHT; 16]);
+
+        map.fill_rect(0.0, 0.0, 2.0, 2.0, 1e30).unwrap();
+        map.fill_ellipse(3.0, 3.0, 0.5, 0.5, -5.0).unwrap();
+        assert_eq!(map.values[0], MAX_WEIGHT);
+        assert_eq!(map.values[15], 0.0);
+    }
+}
@@ -1,4 +1,4 @@
-import init, { DesmosOptimizer } from '../../rust/pkg/rust_core.js';
+import init, { DesmosOptimizer, FidelityMode, OutputMode, ShapeKind } from '../../rust/pkg/rust_core.js';
 
 const LOG_INTERVAL = 50;
 const FRAME_TIME_MS = 12;
@@ -15,9 +15,13 @@ const processBtn = document.getElementById('processBtn');
 const output = document.getElementById('output');
 const shapeCountSlider = document.getElementById('shapeCount');
 const shapeCountVal = document.getElementById('shapeCountVal');
+const seedInput = document.getElementById('seed');
+const canvasPreview = document.getElementById('canvasPreview');
 const logConsole = document.getElementById('logConsole');
 
 let currentFileData = null;
+let currentSvg = null;
+let currentPng = null;
 
 async function run() {
     await init();
@@ -111,6 +115,49 @@ if (copyBtn) {
     });
 }
 
+const svgBtn = document.getElementById('svgBtn');
+if (svgBtn) {
+    svgBtn.addEventListener('click', () => {
+        if (!currentSvg) return;
+        const url = URL.createObjectURL(new Blob([currentSvg], { type: 'image/svg+xml' }));
+        const link = document.createElement('a');
+        link.href = url;
+        link.download = 'eidos.svg';
+        link.click();
+        URL.revokeObjectURL(url);
+    });
+}
+
+const pngBtn = document.getElementById('pngBtn');
+if (pngBtn) {
+    pngBtn.addEventListener('click', () => {
+        if (!currentPng) return;
+        const url = URL.createObjectURL(new Blob([currentPng], { type: 'image/png' }));
+        const link = document.createElement('a');
+        link.href = url;
+        link.download = 'eidos.png';
+        link.click();
+        URL.revokeObjectURL(url);
+    });
+}
+
+function drawPreview(optimizer) {
+    if (!canvasPreview) return;
+    const width = optimizer.width();
+    const height = optimizer.height();
+    if (canvasPreview.width !== width || canvasPreview.height !== height) {
+        canvasPreview.width = width;
+        canvasPreview.height = height;
+    }
+    const pixels = new Uint8ClampedArray(optimizer.get_canvas_pixels());
+    canvasPreview.getContext('2d').putImageData(new ImageData(pixels, width, height), 0, 0);
+}
+
+function readSeed() {
+    const value = seedInput?.value.trim();
+    if (value) return BigInt.asUintN(64, BigInt(value));
+    return BigInt(Math.floor(Math.random() * Number.MAX_SAFE_INTEGER));
+}
 
 function setButtonText(text) {
     processBtn.innerHTML = `<span class="btn-text">${text}</span><div class="btn-glow"></div>`;
@@ -122,24 +169,38 @@ if (processBtn) {
         
         const shapes = parseInt(shapeCountSlider.value);
         const detailLevel = document.getElementById('detailLevel').value;
-        let fidelityMode = 0;
-        if (detailLevel === 'high') fidelityMode = 1;
-        if (detailLevel === 'super') fidelityMode = 2;
-        if (detailLevel === 'hyper') fidelityMode = 3;
+        const shapeKind = document.getElementById('primitive').value === 'triangle'
+            ? ShapeKind.Triangle
+            : ShapeKind.Ellipse;
+        const outputMode = document.getElementById('outputFormat').value === 'lists'
+            ? OutputMode.Lists
+            : OutputMode.Expressions;
+        
+        let seed;
+        try {
+            seed = readSeed();
+        } catch (e) {
+            log(`Invalid seed: ${seedInput.value}`, 'warning');
+            return;
+        }
         
-        log(`Starting optimization... (${shapes} shapes, Mode: ${detailLevel})`);
+        log(`Starting optimization... (${shapes} shapes, Mode: ${detailLevel}, Seed: ${seed})`);
         processBtn.disabled = true;
         processBtn.innerHTML = '<span class="btn-text">Initializing...</span>';
         output.value = "";
+        currentSvg = null;
+        currentPng = null;
+        if (svgBtn) svgBtn.disabled = true;
+        if (pngBtn) pngBtn.disabled = true;
         
         await new Promise(r => setTimeout(r, UI_YIELD_MS));
 
         try {
-            let optimizer = new DesmosOptimizer(currentFileData, shapes, fidelityMode);
+            let optimizer = new DesmosOptimizer(currentFileData, shapes, FidelityMode.parse(detailLevel), seed, shapeKind);
             let done = false;
             let batchSize = STANDARD_BATCH_SIZE;
-            if (fidelityMode === 1 || fidelityMode === 2) batchSize = HIGH_FIDELITY_BATCH_SIZE;
-            if (fidelityMode === 3) batchSize = HYPER_FIDELITY_BATCH_SIZE;
+            if (detailLevel === 'high' || detailLevel === 'super') batchSize = HIGH_FIDELITY_BATCH_SIZE;
+            if (detailLevel === 'hyper') batchSize = HYPER_FIDELITY_BATCH_SIZE;
             
             processBtn.innerHTML = `<span class="btn-text">Optimizing...</span>`;
             
@@ -151,6 +212,8 @@ if (processBtn) {
                         done = optimizer.step(batchSize);
                         if (done) break;
                     }
+                    
+                    drawPreview(optimizer);
 
                     if (!done) {
                         requestAnimationFrame(evolve);
@@ -165,8 +228,9 @@ if (processBtn) {
             }
             
             function finish() {
-                log("Optimization Complete. Generating final JSON...");
-                const json = optimizer.get_json();
+                log(`Optimization Complete. RMSE: ${optimizer.rmse().toFixed(3)}, PSNR: ${optimizer.psnr().toFixed(2)} dB`);
+                log("Generating final JSON...");
+                const json = optimizer.get_json(outputMode);
                 
                 const sizeBytes = new TextEncoder().encode(json).length;
                 const sizeMB = (sizeBytes / (1024 * 1024)).toFixed(2);
@@ -174,6 +238,12 @@ if (processBtn) {
                 
                 const wrapped = `Calc.setState(${json});`;
                 output.value = wrapped;
+                
+                currentSvg = optimizer.get_svg();
+                if (svgBtn) svgBtn.disabled = false;
+                
+                currentPng = optimizer.render_png();
+                if (pngBtn) pngBtn.disabled = false;
                 log("Ready. Use the Copy button to copy to clipboard.");
                 
                 optimizer.free();